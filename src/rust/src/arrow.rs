@@ -0,0 +1,532 @@
+//! Export parsed columns over the Arrow C Data and C Stream interfaces.
+//!
+//! See <https://arrow.apache.org/docs/format/CDataInterface.html> and
+//! <https://arrow.apache.org/docs/format/CStreamInterface.html>. The whole
+//! result is exported as a single struct array (one record batch).
+
+use std::ffi::{c_char, c_int, c_void, CString};
+use std::ptr;
+
+use chrono::{DateTime, Utc};
+
+use crate::process::Column;
+use crate::time::Floating;
+use crate::wkb;
+
+const ARROW_FLAG_NULLABLE: i64 = 2;
+
+#[repr(C)]
+pub struct ArrowSchema {
+    format: *const c_char,
+    name: *const c_char,
+    metadata: *const c_char,
+    flags: i64,
+    n_children: i64,
+    children: *mut *mut ArrowSchema,
+    dictionary: *mut ArrowSchema,
+    release: Option<unsafe extern "C" fn(*mut ArrowSchema)>,
+    private_data: *mut c_void,
+}
+
+#[repr(C)]
+pub struct ArrowArray {
+    length: i64,
+    null_count: i64,
+    offset: i64,
+    n_buffers: i64,
+    n_children: i64,
+    buffers: *mut *const c_void,
+    children: *mut *mut ArrowArray,
+    dictionary: *mut ArrowArray,
+    release: Option<unsafe extern "C" fn(*mut ArrowArray)>,
+    private_data: *mut c_void,
+}
+
+#[repr(C)]
+pub struct ArrowArrayStream {
+    get_schema: Option<unsafe extern "C" fn(*mut ArrowArrayStream, *mut ArrowSchema) -> c_int>,
+    get_next: Option<unsafe extern "C" fn(*mut ArrowArrayStream, *mut ArrowArray) -> c_int>,
+    get_last_error: Option<unsafe extern "C" fn(*mut ArrowArrayStream) -> *const c_char>,
+    release: Option<unsafe extern "C" fn(*mut ArrowArrayStream)>,
+    private_data: *mut c_void,
+}
+
+/// Owned description of an Arrow field.
+#[derive(Clone)]
+pub struct Field {
+    name: String,
+    format: String,
+    metadata: Vec<(String, String)>,
+    children: Vec<Field>,
+}
+
+impl Field {
+    fn new(name: &str, format: &str) -> Field {
+        Field {
+            name: name.to_string(),
+            format: format.to_string(),
+            metadata: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    fn with_metadata(mut self, key: &str, value: &str) -> Field {
+        self.metadata.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    fn with_children(mut self, children: Vec<Field>) -> Field {
+        self.children = children;
+        self
+    }
+}
+
+/// Owned Arrow array buffers. A `None` buffer is exported as a null pointer.
+pub struct ArrayData {
+    length: usize,
+    null_count: usize,
+    buffers: Vec<Option<Vec<u8>>>,
+    children: Vec<ArrayData>,
+}
+
+/// Convert parsed columns into an Arrow C stream containing one record batch.
+///
+/// Floating timestamps are tagged with the zone they were resolved in, or with
+/// no zone when read as naive times.
+pub fn as_arrow_stream(
+    col_names: Vec<String>,
+    columns: Vec<Column>,
+    floating: Floating,
+) -> ArrowArrayStream {
+    let length = columns.first().map_or(0, Column::len);
+    let (fields, arrays): (Vec<Field>, Vec<ArrayData>) = col_names
+        .iter()
+        .zip(columns)
+        .map(|(name, column)| as_arrow_column(name, column, floating))
+        .unzip();
+
+    let schema = Field::new("", "+s").with_children(fields);
+    let batch = ArrayData {
+        length,
+        null_count: 0,
+        buffers: vec![None],
+        children: arrays,
+    };
+
+    let private = Box::new(StreamPrivate {
+        schema,
+        batch: Some(batch),
+    });
+
+    ArrowArrayStream {
+        get_schema: Some(stream_get_schema),
+        get_next: Some(stream_get_next),
+        get_last_error: Some(stream_get_last_error),
+        release: Some(stream_release),
+        private_data: Box::into_raw(private) as *mut c_void,
+    }
+}
+
+fn as_arrow_column(name: &str, column: Column, floating: Floating) -> (Field, ArrayData) {
+    let dim = column.dim();
+    match column {
+        Column::Boolean(values) | Column::Checkbox(values) => {
+            (Field::new(name, "b"), as_boolean_array(&values))
+        }
+        Column::Number(values) | Column::Money(values) | Column::Percent(values) => {
+            (Field::new(name, "g"), as_float64_array(&values))
+        }
+        Column::FixedTimestamp(values) | Column::Date(values) => {
+            (Field::new(name, "tsu:UTC"), as_timestamp_array(&values))
+        }
+        Column::FloatingTimestamp(values) | Column::CalendarDate(values) => (
+            Field::new(
+                name,
+                &match floating {
+                    Floating::Zoned { tz, .. } => format!("tsu:{}", tz.name()),
+                    Floating::Naive => "tsu:".to_string(),
+                },
+            ),
+            as_timestamp_array(&values),
+        ),
+        Column::Json(values) => (
+            Field::new(name, "U").with_metadata("ARROW:extension:name", "arrow.json"),
+            as_utf8_array(&values),
+        ),
+        Column::Text(values)
+        | Column::RowId(values)
+        | Column::Photo(values)
+        | Column::Document(values)
+        | Column::Html(values)
+        | Column::Email(values)
+        | Column::Blob(values) => (Field::new(name, "U"), as_utf8_array(&values)),
+        Column::Url((urls, descs)) => as_struct_array(
+            name,
+            vec![
+                (Field::new("url", "U"), as_utf8_array(&urls)),
+                (Field::new("description", "U"), as_utf8_array(&descs)),
+            ],
+        ),
+        Column::Phone((numbers, types)) => as_struct_array(
+            name,
+            vec![
+                (Field::new("phone_number", "U"), as_utf8_array(&numbers)),
+                (Field::new("phone_type", "U"), as_utf8_array(&types)),
+            ],
+        ),
+        Column::Point(values) => {
+            as_wkb_array(name, &values, |out, pt| wkb::write_point(out, *pt, dim))
+        }
+        Column::Line(values) => {
+            as_wkb_array(name, &values, |out, l| wkb::write_linestring(out, l, dim))
+        }
+        Column::Polygon(values) => {
+            as_wkb_array(name, &values, |out, p| wkb::write_polygon(out, p, dim))
+        }
+        Column::MultiPoint(values) => {
+            as_wkb_array(name, &values, |out, mp| wkb::write_multipoint(out, mp, dim))
+        }
+        Column::MultiLine(values) => as_wkb_array(name, &values, |out, ml| {
+            wkb::write_multilinestring(out, ml, dim)
+        }),
+        Column::MultiPolygon(values) => as_wkb_array(name, &values, |out, mp| {
+            wkb::write_multipolygon(out, mp, dim)
+        }),
+        Column::Location(location) => as_struct_array(
+            name,
+            vec![
+                as_wkb_array("geometry", &location.coords, |out, pt| {
+                    wkb::write_point(out, *pt, dim)
+                }),
+                (
+                    Field::new("address", "U"),
+                    as_utf8_array(&location.addresses),
+                ),
+                (Field::new("city", "U"), as_utf8_array(&location.cities)),
+                (Field::new("state", "U"), as_utf8_array(&location.states)),
+                (Field::new("zip", "U"), as_utf8_array(&location.zips)),
+            ],
+        ),
+    }
+}
+
+/// Build a validity bitmap, returning `None` when every value is present.
+fn validity<T>(values: &[Option<T>]) -> (Option<Vec<u8>>, usize) {
+    let null_count = values.iter().filter(|v| v.is_none()).count();
+    if null_count == 0 {
+        return (None, 0);
+    }
+    let bitmap = bitmap(values.iter().map(Option::is_some), values.len());
+    (Some(bitmap), null_count)
+}
+
+fn bitmap(bits: impl Iterator<Item = bool>, len: usize) -> Vec<u8> {
+    let mut out = vec![0u8; (len + 7) / 8];
+    for (i, bit) in bits.enumerate() {
+        if bit {
+            out[i / 8] |= 1 << (i % 8);
+        }
+    }
+    out
+}
+
+fn as_boolean_array(values: &[Option<bool>]) -> ArrayData {
+    let (validity, null_count) = validity(values);
+    let data = bitmap(values.iter().map(|v| v.unwrap_or(false)), values.len());
+    ArrayData {
+        length: values.len(),
+        null_count,
+        buffers: vec![validity, Some(data)],
+        children: Vec::new(),
+    }
+}
+
+fn as_float64_array(values: &[Option<f64>]) -> ArrayData {
+    let (validity, null_count) = validity(values);
+    let data = values
+        .iter()
+        .flat_map(|v| v.unwrap_or(0.0).to_le_bytes())
+        .collect();
+    ArrayData {
+        length: values.len(),
+        null_count,
+        buffers: vec![validity, Some(data)],
+        children: Vec::new(),
+    }
+}
+
+/// Timestamps are exported as microseconds since the epoch.
+fn as_timestamp_array(values: &[Option<DateTime<Utc>>]) -> ArrayData {
+    let (validity, null_count) = validity(values);
+    let data = values
+        .iter()
+        .flat_map(|v| {
+            v.map_or(0, |instant| instant.timestamp_micros())
+                .to_le_bytes()
+        })
+        .collect();
+    ArrayData {
+        length: values.len(),
+        null_count,
+        buffers: vec![validity, Some(data)],
+        children: Vec::new(),
+    }
+}
+
+/// Build a large (64-bit offset) variable-length array from byte strings.
+fn as_large_binary_array<'a>(values: impl Iterator<Item = Option<&'a [u8]>>) -> ArrayData {
+    let mut offsets: Vec<u8> = 0i64.to_le_bytes().to_vec();
+    let mut data = Vec::new();
+    let mut present = Vec::new();
+    for value in values {
+        if let Some(bytes) = value {
+            data.extend_from_slice(bytes);
+        }
+        present.push(value.map(|_| ()));
+        offsets.extend_from_slice(&(data.len() as i64).to_le_bytes());
+    }
+    let (validity, null_count) = validity(&present);
+    ArrayData {
+        length: present.len(),
+        null_count,
+        buffers: vec![validity, Some(offsets), Some(data)],
+        children: Vec::new(),
+    }
+}
+
+fn as_utf8_array(values: &[Option<String>]) -> ArrayData {
+    as_large_binary_array(values.iter().map(|v| v.as_deref().map(str::as_bytes)))
+}
+
+/// Encode geometries as a GeoArrow WKB column in longitude/latitude order.
+fn as_wkb_array<T>(
+    name: &str,
+    values: &[Option<T>],
+    write: impl Fn(&mut Vec<u8>, &T),
+) -> (Field, ArrayData) {
+    let encoded: Vec<Option<Vec<u8>>> = values
+        .iter()
+        .map(|v| {
+            v.as_ref().map(|geom| {
+                let mut out = Vec::new();
+                write(&mut out, geom);
+                out
+            })
+        })
+        .collect();
+
+    let field = Field::new(name, "Z")
+        .with_metadata("ARROW:extension:name", "geoarrow.wkb")
+        .with_metadata("ARROW:extension:metadata", r#"{"crs":"OGC:CRS84"}"#);
+    let array = as_large_binary_array(encoded.iter().map(|v| v.as_deref()));
+    (field, array)
+}
+
+fn as_struct_array(name: &str, children: Vec<(Field, ArrayData)>) -> (Field, ArrayData) {
+    let length = children.first().map_or(0, |(_, a)| a.length);
+    let (fields, arrays): (Vec<Field>, Vec<ArrayData>) = children.into_iter().unzip();
+    let field = Field::new(name, "+s").with_children(fields);
+    let array = ArrayData {
+        length,
+        null_count: 0,
+        buffers: vec![None],
+        children: arrays,
+    };
+    (field, array)
+}
+
+// Schema export ---------------------------------------------------------------
+
+struct SchemaPrivate {
+    format: CString,
+    name: CString,
+    metadata: Option<Vec<u8>>,
+    children: Vec<*mut ArrowSchema>,
+}
+
+/// Encode field metadata in the binary layout required by the C Data
+/// interface.
+fn encode_metadata(metadata: &[(String, String)]) -> Option<Vec<u8>> {
+    if metadata.is_empty() {
+        return None;
+    }
+    let mut out = Vec::new();
+    out.extend_from_slice(&(metadata.len() as i32).to_ne_bytes());
+    for (key, value) in metadata {
+        out.extend_from_slice(&(key.len() as i32).to_ne_bytes());
+        out.extend_from_slice(key.as_bytes());
+        out.extend_from_slice(&(value.len() as i32).to_ne_bytes());
+        out.extend_from_slice(value.as_bytes());
+    }
+    Some(out)
+}
+
+fn export_schema(field: &Field) -> ArrowSchema {
+    let mut children: Vec<*mut ArrowSchema> = field
+        .children
+        .iter()
+        .map(|child| Box::into_raw(Box::new(export_schema(child))))
+        .collect();
+    let children_ptr = children.as_mut_ptr();
+
+    let private = Box::new(SchemaPrivate {
+        format: CString::new(field.format.as_str()).unwrap_or_default(),
+        name: CString::new(field.name.as_str()).unwrap_or_default(),
+        metadata: encode_metadata(&field.metadata),
+        children,
+    });
+
+    ArrowSchema {
+        format: private.format.as_ptr(),
+        name: private.name.as_ptr(),
+        metadata: private
+            .metadata
+            .as_ref()
+            .map_or(ptr::null(), |m| m.as_ptr() as *const c_char),
+        flags: ARROW_FLAG_NULLABLE,
+        n_children: field.children.len() as i64,
+        children: children_ptr,
+        dictionary: ptr::null_mut(),
+        release: Some(release_schema),
+        private_data: Box::into_raw(private) as *mut c_void,
+    }
+}
+
+unsafe extern "C" fn release_schema(schema: *mut ArrowSchema) {
+    if schema.is_null() || (*schema).release.is_none() {
+        return;
+    }
+    let private = Box::from_raw((*schema).private_data as *mut SchemaPrivate);
+    for child in private.children.iter() {
+        if let Some(release) = (**child).release {
+            release(*child);
+        }
+        drop(Box::from_raw(*child));
+    }
+    (*schema).release = None;
+}
+
+// Array export ----------------------------------------------------------------
+
+struct ArrayPrivate {
+    _buffers: Vec<Option<Vec<u8>>>,
+    _buffer_ptrs: Vec<*const c_void>,
+    children: Vec<*mut ArrowArray>,
+}
+
+fn export_array(array: ArrayData) -> ArrowArray {
+    let mut children: Vec<*mut ArrowArray> = array
+        .children
+        .into_iter()
+        .map(|child| Box::into_raw(Box::new(export_array(child))))
+        .collect();
+    let mut buffer_ptrs: Vec<*const c_void> = array
+        .buffers
+        .iter()
+        .map(|b| {
+            b.as_ref()
+                .map_or(ptr::null(), |b| b.as_ptr() as *const c_void)
+        })
+        .collect();
+
+    let n_buffers = buffer_ptrs.len() as i64;
+    let n_children = children.len() as i64;
+    let buffers_ptr = buffer_ptrs.as_mut_ptr();
+    let children_ptr = children.as_mut_ptr();
+
+    let private = Box::new(ArrayPrivate {
+        _buffers: array.buffers,
+        _buffer_ptrs: buffer_ptrs,
+        children,
+    });
+
+    ArrowArray {
+        length: array.length as i64,
+        null_count: array.null_count as i64,
+        offset: 0,
+        n_buffers,
+        n_children,
+        buffers: buffers_ptr,
+        children: children_ptr,
+        dictionary: ptr::null_mut(),
+        release: Some(release_array),
+        private_data: Box::into_raw(private) as *mut c_void,
+    }
+}
+
+unsafe extern "C" fn release_array(array: *mut ArrowArray) {
+    if array.is_null() || (*array).release.is_none() {
+        return;
+    }
+    let private = Box::from_raw((*array).private_data as *mut ArrayPrivate);
+    for child in private.children.iter() {
+        if let Some(release) = (**child).release {
+            release(*child);
+        }
+        drop(Box::from_raw(*child));
+    }
+    (*array).release = None;
+}
+
+// Stream export ---------------------------------------------------------------
+
+struct StreamPrivate {
+    schema: Field,
+    batch: Option<ArrayData>,
+}
+
+unsafe extern "C" fn stream_get_schema(
+    stream: *mut ArrowArrayStream,
+    out: *mut ArrowSchema,
+) -> c_int {
+    let private = &*((*stream).private_data as *const StreamPrivate);
+    ptr::write(out, export_schema(&private.schema));
+    0
+}
+
+unsafe extern "C" fn stream_get_next(stream: *mut ArrowArrayStream, out: *mut ArrowArray) -> c_int {
+    let private = &mut *((*stream).private_data as *mut StreamPrivate);
+    match private.batch.take() {
+        Some(batch) => ptr::write(out, export_array(batch)),
+        // a released array marks the end of the stream
+        None => ptr::write(
+            out,
+            ArrowArray {
+                length: 0,
+                null_count: 0,
+                offset: 0,
+                n_buffers: 0,
+                n_children: 0,
+                buffers: ptr::null_mut(),
+                children: ptr::null_mut(),
+                dictionary: ptr::null_mut(),
+                release: None,
+                private_data: ptr::null_mut(),
+            },
+        ),
+    }
+    0
+}
+
+unsafe extern "C" fn stream_get_last_error(_stream: *mut ArrowArrayStream) -> *const c_char {
+    ptr::null()
+}
+
+unsafe extern "C" fn stream_release(stream: *mut ArrowArrayStream) {
+    if stream.is_null() || (*stream).release.is_none() {
+        return;
+    }
+    drop(Box::from_raw((*stream).private_data as *mut StreamPrivate));
+    (*stream).release = None;
+}
+
+/// Move a stream into memory allocated by the consumer, such as a
+/// `nanoarrow_array_stream` created with
+/// `nanoarrow::nanoarrow_allocate_array_stream()`.
+///
+/// # Safety
+///
+/// `addr` must point to a writable, released `ArrowArrayStream`.
+pub unsafe fn export_stream(stream: ArrowArrayStream, addr: *mut ArrowArrayStream) {
+    ptr::write(addr, stream);
+}
@@ -0,0 +1,75 @@
+//! Splitting of Socrata CSV pages into records.
+
+/// Reads the records of a CSV page as Socrata writes them: fields separated
+/// by commas, quoted with `"` when needed, with quotes inside quoted fields
+/// doubled, and records ended by `\n` or `\r\n`.
+pub struct Records<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Records<'a> {
+    pub fn new(text: &'a str) -> Records<'a> {
+        // skip a byte order mark
+        let pos = if text.starts_with('\u{feff}') { 3 } else { 0 };
+        Records { text, pos }
+    }
+
+    /// Byte offset of the next record.
+    pub fn offset(&self) -> usize {
+        self.pos
+    }
+
+    /// Read the next record into `fields`, returning `false` at the end of
+    /// the page. A blank line is a record with one empty field.
+    pub fn next_record(&mut self, fields: &mut Vec<String>) -> Result<bool, String> {
+        fields.clear();
+        if self.pos >= self.text.len() {
+            return Ok(false);
+        }
+
+        loop {
+            fields.push(self.field()?);
+            let rest = &self.text[self.pos..];
+            if rest.starts_with(',') {
+                self.pos += 1;
+            } else if rest.starts_with('\n') {
+                self.pos += 1;
+                return Ok(true);
+            } else if rest.starts_with("\r\n") {
+                self.pos += 2;
+                return Ok(true);
+            } else if rest.is_empty() {
+                return Ok(true);
+            } else {
+                return Err("unexpected character after a quoted field".to_string());
+            }
+        }
+    }
+
+    fn field(&mut self) -> Result<String, String> {
+        let rest = &self.text[self.pos..];
+        if !rest.starts_with('"') {
+            let len = rest.find([',', '\n', '\r']).unwrap_or(rest.len());
+            self.pos += len;
+            return Ok(rest[..len].to_string());
+        }
+
+        let mut field = String::new();
+        let mut start = self.pos + 1;
+        loop {
+            let end = match self.text[start..].find('"') {
+                Some(i) => start + i,
+                None => return Err("unterminated quoted field".to_string()),
+            };
+            field.push_str(&self.text[start..end]);
+            if self.text[end + 1..].starts_with('"') {
+                field.push('"');
+                start = end + 2;
+            } else {
+                self.pos = end + 1;
+                return Ok(field);
+            }
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! Change data capture between two snapshots of a dataset, matching rows by a
+//! key column and comparing their parsed values.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+
+use crate::error::ParseError;
+use crate::geom::Coord;
+use crate::process::{Column, LocationColumn};
+
+/// Rows that differ between an old and a new snapshot.
+pub struct Changes {
+    /// Rows of the new snapshot whose key is not in the old one.
+    pub added: Vec<usize>,
+    /// Rows of the old snapshot whose key is not in the new one.
+    pub deleted: Vec<usize>,
+    /// Rows of the new snapshot whose values differ from the old row with the
+    /// same key, with the indices of the columns that differ.
+    pub modified: Vec<(usize, Vec<usize>)>,
+}
+
+/// Compare two snapshots read with the same schema, matching rows by the
+/// values of the column `key`.
+pub fn diff(
+    col_names: &[String],
+    old: &[Column],
+    new: &[Column],
+    key: &str,
+) -> Result<Changes, ParseError> {
+    let error = |message: String| ParseError::Key {
+        column: key.to_string(),
+        message,
+    };
+
+    let k = col_names
+        .iter()
+        .position(|name| name == key)
+        .ok_or_else(|| error("no such column".to_string()))?;
+    let old_keys = key_values(&old[k], "old").map_err(error)?;
+    let new_keys = key_values(&new[k], "new").map_err(error)?;
+
+    let mut index = HashMap::with_capacity(old_keys.len());
+    for (i, key) in old_keys.iter().enumerate() {
+        if index.insert(key.as_str(), i).is_some() {
+            return Err(error(format!(
+                "`{}` is duplicated in the old snapshot",
+                key
+            )));
+        }
+    }
+
+    let mut matched = vec![false; old_keys.len()];
+    let mut seen = HashMap::with_capacity(new_keys.len());
+    let mut added = Vec::new();
+    let mut modified = Vec::new();
+    for (j, key) in new_keys.iter().enumerate() {
+        if seen.insert(key.as_str(), j).is_some() {
+            return Err(error(format!(
+                "`{}` is duplicated in the new snapshot",
+                key
+            )));
+        }
+        match index.get(key.as_str()) {
+            Some(&i) => {
+                matched[i] = true;
+                let changed: Vec<usize> = (0..old.len())
+                    .filter(|&c| !old[c].same_at(i, &new[c], j))
+                    .collect();
+                if !changed.is_empty() {
+                    modified.push((j, changed));
+                }
+            }
+            None => added.push(j),
+        }
+    }
+    let deleted = (0..old_keys.len()).filter(|&i| !matched[i]).collect();
+
+    Ok(Changes {
+        added,
+        deleted,
+        modified,
+    })
+}
+
+/// The values of a key column as strings, failing on columns that cannot
+/// identify rows and on missing values.
+fn key_values(column: &Column, snapshot: &str) -> Result<Vec<String>, String> {
+    let values: Vec<Option<String>> = match column {
+        Column::Text(v) | Column::RowId(v) | Column::Email(v) => v.clone(),
+        Column::Number(v) => v.iter().map(|v| v.map(|v| v.to_string())).collect(),
+        _ => return Err("must be a text, row identifier or number column".to_string()),
+    };
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(row, value)| {
+            value.ok_or_else(|| format!("row {} of the {} snapshot is missing", row + 1, snapshot))
+        })
+        .collect()
+}
+
+/// Equality of parsed values, where missing ordinates and NaN numbers equal
+/// each other.
+trait Same {
+    fn same(&self, other: &Self) -> bool;
+}
+
+impl Same for bool {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl Same for DateTime<Utc> {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl Same for f64 {
+    fn same(&self, other: &Self) -> bool {
+        self == other || (self.is_nan() && other.is_nan())
+    }
+}
+
+impl Same for String {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl Same for Coord {
+    fn same(&self, other: &Self) -> bool {
+        self.x.same(&other.x)
+            && self.y.same(&other.y)
+            && self.z.same(&other.z)
+            && self.m.same(&other.m)
+    }
+}
+
+impl<T: Same> Same for Option<T> {
+    fn same(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Some(a), Some(b)) => a.same(b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T: Same> Same for Vec<T> {
+    fn same(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().zip(other).all(|(a, b)| a.same(b))
+    }
+}
+
+fn pick<T: Clone>(values: &[T], rows: &[usize]) -> Vec<T> {
+    rows.iter().map(|&row| values[row].clone()).collect()
+}
+
+impl Column {
+    /// Whether row `i` of this column holds the same value as row `j` of
+    /// `other`, comparing timestamps as instants, numbers as numbers and
+    /// geometries by their coordinates.
+    fn same_at(&self, i: usize, other: &Column, j: usize) -> bool {
+        match (self, other) {
+            (Column::Boolean(a), Column::Boolean(b))
+            | (Column::Checkbox(a), Column::Checkbox(b)) => a[i].same(&b[j]),
+            (Column::Number(a), Column::Number(b))
+            | (Column::Money(a), Column::Money(b))
+            | (Column::Percent(a), Column::Percent(b)) => a[i].same(&b[j]),
+            (Column::FixedTimestamp(a), Column::FixedTimestamp(b))
+            | (Column::FloatingTimestamp(a), Column::FloatingTimestamp(b))
+            | (Column::Date(a), Column::Date(b))
+            | (Column::CalendarDate(a), Column::CalendarDate(b)) => a[i].same(&b[j]),
+            (Column::Text(a), Column::Text(b))
+            | (Column::RowId(a), Column::RowId(b))
+            | (Column::Photo(a), Column::Photo(b))
+            | (Column::Document(a), Column::Document(b))
+            | (Column::Html(a), Column::Html(b))
+            | (Column::Email(a), Column::Email(b))
+            | (Column::Blob(a), Column::Blob(b))
+            | (Column::Json(a), Column::Json(b)) => a[i].same(&b[j]),
+            (Column::Url((a1, a2)), Column::Url((b1, b2)))
+            | (Column::Phone((a1, a2)), Column::Phone((b1, b2))) => {
+                a1[i].same(&b1[j]) && a2[i].same(&b2[j])
+            }
+            (Column::Point(a), Column::Point(b)) => a[i].same(&b[j]),
+            (Column::Line(a), Column::Line(b)) | (Column::MultiPoint(a), Column::MultiPoint(b)) => {
+                a[i].same(&b[j])
+            }
+            (Column::Polygon(a), Column::Polygon(b))
+            | (Column::MultiLine(a), Column::MultiLine(b)) => a[i].same(&b[j]),
+            (Column::MultiPolygon(a), Column::MultiPolygon(b)) => a[i].same(&b[j]),
+            (Column::Location(a), Column::Location(b)) => {
+                a.coords[i].same(&b.coords[j])
+                    && a.addresses[i].same(&b.addresses[j])
+                    && a.cities[i].same(&b.cities[j])
+                    && a.states[i].same(&b.states[j])
+                    && a.zips[i].same(&b.zips[j])
+            }
+            _ => false,
+        }
+    }
+
+    /// A new column with the values of `rows`, in order.
+    pub fn take(&self, rows: &[usize]) -> Column {
+        match self {
+            Column::Boolean(v) => Column::Boolean(pick(v, rows)),
+            Column::Number(v) => Column::Number(pick(v, rows)),
+            Column::FixedTimestamp(v) => Column::FixedTimestamp(pick(v, rows)),
+            Column::FloatingTimestamp(v) => Column::FloatingTimestamp(pick(v, rows)),
+            Column::Text(v) => Column::Text(pick(v, rows)),
+            Column::RowId(v) => Column::RowId(pick(v, rows)),
+            Column::Url((a, b)) => Column::Url((pick(a, rows), pick(b, rows))),
+            Column::Photo(v) => Column::Photo(pick(v, rows)),
+            Column::Document(v) => Column::Document(pick(v, rows)),
+            Column::Checkbox(v) => Column::Checkbox(pick(v, rows)),
+            Column::Money(v) => Column::Money(pick(v, rows)),
+            Column::Percent(v) => Column::Percent(pick(v, rows)),
+            Column::Date(v) => Column::Date(pick(v, rows)),
+            Column::CalendarDate(v) => Column::CalendarDate(pick(v, rows)),
+            Column::Html(v) => Column::Html(pick(v, rows)),
+            Column::Email(v) => Column::Email(pick(v, rows)),
+            Column::Phone((a, b)) => Column::Phone((pick(a, rows), pick(b, rows))),
+            Column::Blob(v) => Column::Blob(pick(v, rows)),
+            Column::Json(v) => Column::Json(pick(v, rows)),
+            Column::Point(v) => Column::Point(pick(v, rows)),
+            Column::Line(v) => Column::Line(pick(v, rows)),
+            Column::Polygon(v) => Column::Polygon(pick(v, rows)),
+            Column::MultiPoint(v) => Column::MultiPoint(pick(v, rows)),
+            Column::MultiLine(v) => Column::MultiLine(pick(v, rows)),
+            Column::MultiPolygon(v) => Column::MultiPolygon(pick(v, rows)),
+            Column::Location(location) => Column::Location(LocationColumn {
+                coords: pick(&location.coords, rows),
+                addresses: pick(&location.addresses, rows),
+                cities: pick(&location.cities, rows),
+                states: pick(&location.states, rows),
+                zips: pick(&location.zips, rows),
+            }),
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::fmt;
+
+use extendr_api::prelude::*;
+
+/// Failures that can occur while parsing Socrata responses.
+///
+/// These are returned to R as a `socratadata_parse_error` object rather than
+/// panicking, so the R side can signal a classed condition.
+#[derive(Clone, Debug)]
+pub enum ParseError {
+    /// An argument passed from R is invalid, or a reader is used after it was
+    /// finished.
+    Argument { message: String },
+    /// A response header (`X-SODA2-Fields` or `X-SODA2-Types`) is not a JSON
+    /// array of strings.
+    Header { header: String, message: String },
+    /// A page body is not a raw vector.
+    RawPage { page: usize },
+    /// Writing a file failed.
+    Io { path: String, message: String },
+    /// Reading or decompressing a page file failed.
+    File { path: String, message: String },
+    /// A parser thread panicked or stopped before finishing its pages.
+    Thread,
+    /// The key column of a snapshot diff is absent, of a type that cannot
+    /// identify rows, or has missing or duplicated values.
+    Key { column: String, message: String },
+    /// A page body is not valid JSON or does not have the expected shape.
+    Json {
+        page: usize,
+        offset: usize,
+        column: Option<(String, String)>,
+        message: String,
+    },
+    /// A page body is not a valid CSV file of the expected shape.
+    Csv {
+        page: usize,
+        offset: usize,
+        column: Option<(String, String)>,
+        message: String,
+    },
+}
+
+impl ParseError {
+    /// Build a [`ParseError::Argument`].
+    pub fn argument(message: impl Into<String>) -> ParseError {
+        ParseError::Argument {
+            message: message.into(),
+        }
+    }
+
+    /// Build a [`ParseError::Json`] from a serde error, resolving its
+    /// line/column position to a byte offset within the page.
+    pub fn json(
+        page: usize,
+        bytes: &[u8],
+        column: Option<(String, String)>,
+        err: serde_json::Error,
+    ) -> ParseError {
+        ParseError::Json {
+            page,
+            offset: byte_offset(bytes, err.line(), err.column()),
+            column,
+            message: err.to_string(),
+        }
+    }
+
+    /// Convert into a list of class `socratadata_parse_error` with the fields
+    /// `message`, `page`, `offset`, `column` and `type`.
+    pub fn into_robj(self) -> Robj {
+        let message = self.to_string();
+        let (page, offset, column, col_type) = match self {
+            ParseError::Argument { .. }
+            | ParseError::Header { .. }
+            | ParseError::Io { .. }
+            | ParseError::File { .. }
+            | ParseError::Thread => (None, None, None, None),
+            ParseError::RawPage { page } => (Some(page), None, None, None),
+            ParseError::Key { column, .. } => (None, None, Some(column), None),
+            ParseError::Json {
+                page,
+                offset,
+                column,
+                ..
+            }
+            | ParseError::Csv {
+                page,
+                offset,
+                column,
+                ..
+            } => match column {
+                Some((name, ty)) => (Some(page), Some(offset), Some(name), Some(ty)),
+                None => (Some(page), Some(offset), None, None),
+            },
+        };
+
+        let mut robj = List::from_names_and_values(
+            ["message", "page", "offset", "column", "type"],
+            [
+                r!(message),
+                r!(page.map_or(Rint::na(), |p| Rint::from(p as i32))),
+                r!(offset.map_or(Rfloat::na(), |o| Rfloat::from(o as f64))),
+                r!(vec![column.as_deref()]),
+                r!(vec![col_type.as_deref()]),
+            ],
+        )
+        .unwrap()
+        .into_robj();
+        robj.set_class(&["socratadata_parse_error"]).unwrap();
+        robj
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Argument { message } => f.write_str(message),
+            ParseError::Header { header, message } => {
+                write!(f, "Invalid `{}` header: {}", header, message)
+            }
+            ParseError::RawPage { page } => {
+                write!(f, "Page {} is not a raw vector", page)
+            }
+            ParseError::Io { path, message } => {
+                write!(f, "Failed to write `{}`: {}", path, message)
+            }
+            ParseError::File { path, message } => {
+                write!(f, "Failed to read `{}`: {}", path, message)
+            }
+            ParseError::Thread => f.write_str("A parser thread stopped unexpectedly"),
+            ParseError::Key { column, message } => {
+                write!(f, "Invalid key column `{}`: {}", column, message)
+            }
+            ParseError::Json {
+                page,
+                offset,
+                column,
+                message,
+            }
+            | ParseError::Csv {
+                page,
+                offset,
+                column,
+                message,
+            } => match column {
+                Some((name, ty)) => write!(
+                    f,
+                    "Page {} at byte {}, column `{}` ({}): {}",
+                    page, offset, name, ty, message
+                ),
+                None => write!(f, "Page {} at byte {}: {}", page, offset, message),
+            },
+        }
+    }
+}
+
+/// Convert a 1-based line/column position reported by serde_json into a
+/// 0-based byte offset.
+fn byte_offset(bytes: &[u8], line: usize, column: usize) -> usize {
+    let line_start = if line <= 1 {
+        0
+    } else {
+        bytes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| **b == b'\n')
+            .nth(line - 2)
+            .map_or(bytes.len(), |(i, _)| i + 1)
+    };
+
+    (line_start + column.saturating_sub(1)).min(bytes.len())
+}
@@ -0,0 +1,79 @@
+//! Reading archived response pages from local files, which may be gzip or
+//! zstd compressed.
+
+use std::fs::File;
+use std::io::Read;
+use std::ops::Deref;
+
+use flate2::read::MultiGzDecoder;
+use memmap2::Mmap;
+use ruzstd::StreamingDecoder;
+
+use crate::error::ParseError;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// The contents of a page file: memory-mapped if it is stored as is, or
+/// decompressed into memory.
+pub enum PageFile {
+    Mapped(Mmap),
+    Decompressed(Vec<u8>),
+}
+
+impl Deref for PageFile {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            PageFile::Mapped(mmap) => mmap,
+            PageFile::Decompressed(bytes) => bytes,
+        }
+    }
+}
+
+impl PageFile {
+    /// Open a page file, detecting gzip and zstd compression from its magic
+    /// bytes rather than its extension.
+    pub fn open(path: &str) -> Result<PageFile, ParseError> {
+        let error = |message: String| ParseError::File {
+            path: path.to_string(),
+            message,
+        };
+
+        let file = File::open(path).map_err(|err| error(err.to_string()))?;
+        let len = file.metadata().map_err(|err| error(err.to_string()))?.len();
+        if len == 0 {
+            return Ok(PageFile::Decompressed(Vec::new()));
+        }
+        // SAFETY: the map is only read, and archived pages are not expected to
+        // change while they are parsed
+        let mmap = unsafe { Mmap::map(&file) }.map_err(|err| error(err.to_string()))?;
+
+        if mmap.starts_with(GZIP_MAGIC) {
+            let mut bytes = Vec::new();
+            MultiGzDecoder::new(&mmap[..])
+                .read_to_end(&mut bytes)
+                .map_err(|err| error(format!("invalid gzip data: {}", err)))?;
+            Ok(PageFile::Decompressed(bytes))
+        } else if mmap.starts_with(ZSTD_MAGIC) {
+            decompress_zstd(&mmap)
+                .map(PageFile::Decompressed)
+                .map_err(|message| error(format!("invalid zstd data: {}", message)))
+        } else {
+            Ok(PageFile::Mapped(mmap))
+        }
+    }
+}
+
+/// Decompress every frame of a zstd stream.
+fn decompress_zstd(mut data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    while !data.is_empty() {
+        let mut decoder = StreamingDecoder::new(&mut data).map_err(|err| err.to_string())?;
+        decoder
+            .read_to_end(&mut bytes)
+            .map_err(|err| err.to_string())?;
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,109 @@
+//! Coordinates shared by the geometry parsers and encoders.
+//!
+//! Socrata sends coordinates in two layouts: GeoJSON positions, which are
+//! always `[longitude, latitude, ...]`, and location columns, which have
+//! separate `latitude` and `longitude` fields. Both are turned into a
+//! [`Coord`] here, so that nothing downstream has to know the axis order.
+
+use serde_json::Value;
+
+/// A coordinate with x (longitude) and y (latitude), and z and m ordinates
+/// that are NaN when absent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Coord {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub m: f64,
+}
+
+impl Coord {
+    /// A coordinate without z or m.
+    pub fn xy(x: f64, y: f64) -> Coord {
+        Coord {
+            x,
+            y,
+            z: f64::NAN,
+            m: f64::NAN,
+        }
+    }
+
+    /// The coordinate of a location column's `latitude` and `longitude`.
+    pub fn from_lat_lon(lat: f64, lon: f64) -> Coord {
+        Coord::xy(lon, lat)
+    }
+
+    /// The coordinate of a GeoJSON position, `[x, y]` with an optional z and
+    /// m, or `None` if it is not an array of 2 to 4 numbers.
+    pub fn from_position(val: &Value) -> Option<Coord> {
+        let arr = val.as_array()?;
+        if !(2..=4).contains(&arr.len()) {
+            return None;
+        }
+        let ordinate = |i: usize| arr.get(i).map_or(Some(f64::NAN), Value::as_f64);
+        Some(Coord {
+            x: arr[0].as_f64()?,
+            y: arr[1].as_f64()?,
+            z: ordinate(2)?,
+            m: ordinate(3)?,
+        })
+    }
+
+    /// Like [`Coord::from_position`], but with NaN for any ordinate that is
+    /// missing or not a number.
+    pub fn from_position_or_nan(val: &Value) -> Coord {
+        let ordinate = |i: usize| val.get(i).and_then(Value::as_f64).unwrap_or(f64::NAN);
+        Coord {
+            x: ordinate(0),
+            y: ordinate(1),
+            z: ordinate(2),
+            m: ordinate(3),
+        }
+    }
+}
+
+/// Coordinate dimensions of a geometry or geometry column.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Dim {
+    pub z: bool,
+    pub m: bool,
+}
+
+impl Dim {
+    pub const XY: Dim = Dim { z: false, m: false };
+
+    /// The smallest dimension that holds every coordinate, so that a column
+    /// mixing 2D and 3D geometries is promoted to 3D.
+    pub fn of<'a>(coords: impl IntoIterator<Item = &'a Coord>) -> Dim {
+        coords.into_iter().fold(Dim::XY, |dim, coord| Dim {
+            z: dim.z || !coord.z.is_nan(),
+            m: dim.m || !coord.m.is_nan(),
+        })
+    }
+
+    /// The sf class of the dimension.
+    pub fn name(self) -> &'static str {
+        match (self.z, self.m) {
+            (false, false) => "XY",
+            (true, false) => "XYZ",
+            (false, true) => "XYM",
+            (true, true) => "XYZM",
+        }
+    }
+
+    pub fn n_ordinates(self) -> usize {
+        2 + self.z as usize + self.m as usize
+    }
+
+    /// The ordinates of a coordinate kept in this dimension.
+    pub fn ordinates(self, coord: Coord) -> impl Iterator<Item = f64> {
+        [
+            Some(coord.x),
+            Some(coord.y),
+            self.z.then_some(coord.z),
+            self.m.then_some(coord.m),
+        ]
+        .into_iter()
+        .flatten()
+    }
+}
@@ -0,0 +1,343 @@
+//! Schema inference from the values of a page, used when the `X-SODA2-Fields`
+//! and `X-SODA2-Types` headers are missing or disagree with the data.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use chrono::{DateTime, NaiveDateTime};
+use serde::de::{DeserializeSeed, IgnoredAny, SeqAccess, Visitor};
+use serde_json::{Map, Value};
+
+use crate::csv::Records;
+use crate::error::ParseError;
+use crate::parse::parse_wkt;
+use crate::process::Column;
+use crate::time::Floating;
+
+/// A field of the sampled rows.
+pub struct SampledField {
+    pub name: String,
+    /// The Socrata datatype inferred from the values, or `None` if every
+    /// sampled value was null.
+    pub ty: Option<&'static str>,
+    /// The non-null sampled values, to check header types against.
+    pub values: Vec<Value>,
+}
+
+/// Fields of the sampled rows.
+pub type InferredSchema = Vec<SampledField>;
+
+/// Infer a Socrata datatype for every field in the first `n_rows` rows of a
+/// raw JSON page.
+pub fn infer_schema(bytes: &[u8], n_rows: usize) -> Result<InferredSchema, ParseError> {
+    let mut de = serde_json::Deserializer::from_slice(bytes);
+    let rows = SampleSeed { n_rows }
+        .deserialize(&mut de)
+        .and_then(|rows| de.end().map(|_| rows))
+        .map_err(|err| ParseError::json(1, bytes, None, err))?;
+
+    Ok(schema_of(rows))
+}
+
+/// Infer a Socrata datatype for every field in the first `n_rows` records of
+/// a raw CSV page, where every value is text and empty fields are null.
+pub fn infer_csv_schema(bytes: &[u8], n_rows: usize) -> Result<InferredSchema, ParseError> {
+    let csv_error = |offset: usize, message: String| ParseError::Csv {
+        page: 1,
+        offset,
+        column: None,
+        message,
+    };
+    let text = std::str::from_utf8(bytes)
+        .map_err(|err| csv_error(err.valid_up_to(), "invalid UTF-8".to_string()))?;
+    let mut records = Records::new(text);
+
+    let mut header = Vec::new();
+    records
+        .next_record(&mut header)
+        .map_err(|message| csv_error(0, message))?;
+
+    let mut rows = Vec::new();
+    let mut fields = Vec::new();
+    while rows.len() < n_rows {
+        let offset = records.offset();
+        match records.next_record(&mut fields) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(message) => return Err(csv_error(offset, message)),
+        }
+        let row: Map<String, Value> = header
+            .iter()
+            .cloned()
+            .zip(fields.drain(..))
+            .map(|(name, field)| {
+                let val = if field.is_empty() {
+                    Value::Null
+                } else {
+                    Value::String(field)
+                };
+                (name, val)
+            })
+            .collect();
+        rows.push(row);
+    }
+
+    Ok(schema_of(rows))
+}
+
+/// Infer a Socrata datatype for every field in the first `n_rows` lines of a
+/// raw NDJSON page, skipping blank lines.
+pub fn infer_ndjson_schema(bytes: &[u8], n_rows: usize) -> Result<InferredSchema, ParseError> {
+    let mut rows = Vec::new();
+    let mut start = 0;
+    for line in bytes.split(|&b| b == b'\n') {
+        let line_start = start;
+        start += line.len() + 1;
+        if rows.len() >= n_rows {
+            break;
+        }
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        let row = serde_json::from_slice(line).map_err(|err| ParseError::Json {
+            page: 1,
+            offset: line_start + err.column().saturating_sub(1),
+            column: None,
+            message: err.to_string(),
+        })?;
+        rows.push(row);
+    }
+
+    Ok(schema_of(rows))
+}
+
+/// Infer a Socrata datatype for every property in the first `n_rows` features
+/// of a raw GeoJSON FeatureCollection. Feature geometries are sampled as a
+/// field named `geometry`.
+pub fn infer_geojson_schema(bytes: &[u8], n_rows: usize) -> Result<InferredSchema, ParseError> {
+    let collection: Value =
+        serde_json::from_slice(bytes).map_err(|err| ParseError::json(1, bytes, None, err))?;
+    let features = collection
+        .get("features")
+        .and_then(Value::as_array)
+        .map_or(&[][..], Vec::as_slice);
+
+    let rows = features
+        .iter()
+        .take(n_rows)
+        .map(|feature| {
+            let mut row = Map::new();
+            row.insert(
+                "geometry".to_string(),
+                feature.get("geometry").cloned().unwrap_or(Value::Null),
+            );
+            if let Some(Value::Object(properties)) = feature.get("properties") {
+                row.extend(properties.clone());
+            }
+            row
+        })
+        .collect();
+
+    Ok(schema_of(rows))
+}
+
+/// Whether a raw GeoJSON page has no features.
+pub fn is_empty_geojson(bytes: &[u8]) -> bool {
+    match serde_json::from_slice::<Value>(bytes) {
+        Ok(collection) => collection
+            .get("features")
+            .and_then(Value::as_array)
+            .map_or(true, Vec::is_empty),
+        Err(_) => false,
+    }
+}
+
+/// Whether a raw CSV page has no records after its header row.
+pub fn is_empty_csv(bytes: &[u8]) -> bool {
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(_) => return false,
+    };
+    let mut records = Records::new(text);
+    let mut fields = Vec::new();
+    !matches!(records.next_record(&mut fields), Ok(true))
+        || !matches!(records.next_record(&mut fields), Ok(true))
+}
+
+/// Infer the type of each field from its non-null values. Fields come in the
+/// order of the first row that has them, and alphabetically within a row, as
+/// `Map` keeps its keys sorted.
+fn schema_of(rows: Vec<Map<String, Value>>) -> InferredSchema {
+    let mut schema: InferredSchema = Vec::new();
+    let mut lookup: HashMap<String, usize> = HashMap::new();
+    for row in rows {
+        for (name, val) in row {
+            let i = *lookup.entry(name.clone()).or_insert_with(|| {
+                schema.push(SampledField {
+                    name,
+                    ty: None,
+                    values: Vec::new(),
+                });
+                schema.len() - 1
+            });
+            if let Some(ty) = infer_value(&val) {
+                let field = &mut schema[i];
+                field.ty = Some(field.ty.map_or(ty, |prev| merge(prev, ty)));
+                field.values.push(val);
+            }
+        }
+    }
+
+    schema
+}
+
+/// Infer a Socrata datatype from a field's values, or `None` if they are all
+/// null.
+pub fn infer_values<'a, I>(values: I) -> Option<&'static str>
+where
+    I: IntoIterator<Item = &'a Value>,
+{
+    values.into_iter().filter_map(infer_value).reduce(merge)
+}
+
+/// The datatype of a Socrata system field, which headers type as `meta_data`
+/// or plain text, or leave out altogether.
+pub fn system_type(name: &str) -> Option<&'static str> {
+    match name {
+        ":id" => Some("row_identifier"),
+        ":created_at" | ":updated_at" => Some("fixed_timestamp"),
+        ":version" => Some("row_version"),
+        _ => None,
+    }
+}
+
+/// Whether every sampled value of a field can be read as the header type
+/// `header`, by the parser of that type. Unknown header types never fit.
+pub fn is_compatible(header: &str, values: &[Value]) -> bool {
+    let mut column = match Column::from_type(header) {
+        Some(column) => column,
+        None => return false,
+    };
+    // floating timestamps are only checked for their format, not their zone
+    values
+        .iter()
+        .all(|val| column.push(Some(val), Floating::Naive, "").is_ok() && !column.last_is_na())
+}
+
+fn infer_value(val: &Value) -> Option<&'static str> {
+    let ty = match val {
+        Value::Null => return None,
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(s) => infer_string(s),
+        Value::Array(_) => "json",
+        Value::Object(map) => infer_object(map),
+    };
+    Some(ty)
+}
+
+fn infer_string(s: &str) -> &'static str {
+    if is_number(s) {
+        "number"
+    } else if DateTime::parse_from_rfc3339(s).is_ok() {
+        "fixed_timestamp"
+    } else if NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f").is_ok() {
+        "floating_timestamp"
+    } else if let Some(wkt) = parse_wkt(s) {
+        wkt.col_type()
+    } else {
+        "text"
+    }
+}
+
+/// Socrata sends numbers as strings, but identifiers such as zip codes look
+/// numeric too, so leading zeros are kept as text.
+fn is_number(s: &str) -> bool {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    let leading_zero = digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.");
+    !leading_zero && s.parse::<f64>().map_or(false, f64::is_finite)
+}
+
+fn infer_object(map: &Map<String, Value>) -> &'static str {
+    if map.contains_key("coordinates") {
+        match map.get("type").and_then(Value::as_str) {
+            Some("Point") => "point",
+            Some("LineString") => "line",
+            Some("Polygon") => "polygon",
+            Some("MultiPoint") => "multipoint",
+            Some("MultiLineString") => "multiline",
+            Some("MultiPolygon") => "multipolygon",
+            _ => "json",
+        }
+    } else if map.contains_key("latitude")
+        || map.contains_key("longitude")
+        || map.contains_key("human_address")
+    {
+        "location"
+    } else if map.contains_key("url") {
+        "url"
+    } else if map.contains_key("phone_number") {
+        "phone"
+    } else if map.contains_key("file_id") {
+        "document"
+    } else {
+        "json"
+    }
+}
+
+/// The most specific type that can hold values of both types.
+fn merge(a: &'static str, b: &'static str) -> &'static str {
+    let is_scalar = |ty| {
+        matches!(
+            ty,
+            "number" | "text" | "fixed_timestamp" | "floating_timestamp"
+        )
+    };
+
+    if a == b {
+        a
+    } else if is_scalar(a) && is_scalar(b) {
+        "text"
+    } else {
+        "json"
+    }
+}
+
+/// Deserializes the first `n_rows` row objects of a page, skipping the rest.
+struct SampleSeed {
+    n_rows: usize,
+}
+
+impl<'de> DeserializeSeed<'de> for SampleSeed {
+    type Value = Vec<Map<String, Value>>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for SampleSeed {
+    type Value = Vec<Map<String, Value>>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an array of row objects")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut rows = Vec::new();
+        while rows.len() < self.n_rows {
+            match seq.next_element::<Map<String, Value>>()? {
+                Some(row) => rows.push(row),
+                None => return Ok(rows),
+            }
+        }
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+        Ok(rows)
+    }
+}
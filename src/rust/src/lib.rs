@@ -1,138 +1,948 @@
+mod arrow;
+mod csv;
+mod diff;
+mod error;
+mod file;
+mod geom;
+mod infer;
+mod parquet;
 mod parse;
+mod pool;
 mod process;
+mod reader;
+mod repair;
+mod rows;
+mod time;
+mod wkb;
+mod wkt;
 
-use chrono_tz::Tz;
+use arrow::{as_arrow_stream, export_stream, ArrowArrayStream};
+use error::ParseError;
 use extendr_api::prelude::*;
-use parse::*;
-use process::{as_rlist, Column};
+use file::PageFile;
+use parquet::ParquetWriter;
+use pool::PagePool;
+use process::{as_rlist, Column, DateColumns, GeometryClass, OutputOptions, TimestampClass};
+use chrono_tz::Tz;
+use reader::{Format, Reader};
 use serde_json::Value;
+use time::{Ambiguous, Floating};
 
+// Parse raw Socrata JSON pages into a named list of R columns.
+//
+// Floating timestamps are resolved in `tz` (the session timezone if empty)
+// following the `ambiguous` policy, or kept as wall-clock times if
+// `naive_floating` is `TRUE`. Floating timestamp columns named in
+// `date_cols`, or if it is `NULL` those whose values are all midnight, are
+// returned as `Date`s. Geometry columns are sf `sfc` lists, or with
+// `geometry_class` "wkb" or "wkt" wk vectors that do not need sf. If
+// `validate_geometry` is `TRUE`, line and polygon geometries are repaired and
+// the list gets a `validity` attribute describing what was repaired. If
+// `keep_extra_fields` is `TRUE`, fields of the rows that are not in the headers
+// are added as columns of the type inferred from their values and listed in
+// the `extra_fields` attribute.
+//
+// On failure a `socratadata_parse_error` object is returned instead of the
+// list, which the R side turns into a classed condition.
 #[extendr]
+#[allow(clippy::too_many_arguments)]
 fn parse_data_json(
     raw_json: List,
     header_col_names: &str,
     header_col_types: &str,
     meta_url: &str,
-) -> List {
-    let col_names: Vec<String> =
-        serde_json::from_str(header_col_names).expect("Failed to parse JSON array");
-    let col_types: Vec<String> =
-        serde_json::from_str(header_col_types).expect("Failed to parse JSON array");
-    let rows: Vec<Value> = raw_json
-        .iter()
-        .flat_map(|(_, robj)| {
-            let bytes = robj.as_raw_slice().unwrap();
-            let parsed: Vec<Value> = serde_json::from_slice(bytes)
-                .expect("Failed to parse JSON");
-            parsed
+    #[default = "1L"] n_threads: i32,
+    #[default = "'POSIXct'"] timestamp_class: &str,
+    #[default = "''"] tz: &str,
+    #[default = "'earliest'"] ambiguous: &str,
+    #[default = "FALSE"] naive_floating: bool,
+    #[default = "NULL"] date_cols: Robj,
+    #[default = "'sf'"] geometry_class: &str,
+    #[default = "FALSE"] validate_geometry: bool,
+    #[default = "FALSE"] keep_extra_fields: bool,
+) -> Robj {
+    into_result_robj(|| {
+        let options = ReadOptions::from_args(
+            timestamp_class,
+            tz,
+            ambiguous,
+            naive_floating,
+            date_cols,
+            geometry_class,
+        )?;
+        let reader = new_reader(
+            header_col_names,
+            header_col_types,
+            meta_url,
+            options.floating,
+            validate_geometry,
+            Format::Json,
+            keep_extra_fields,
+        )?;
+        read_pages(raw_json, reader, n_threads)?.finish(&options.output)
+    })
+}
+
+// Parse raw Socrata CSV pages, each starting with a row of field names, into a
+// named list of R columns. Options are handled as in `parse_data_json()`.
+#[extendr]
+#[allow(clippy::too_many_arguments)]
+fn parse_data_csv(
+    raw_csv: List,
+    header_col_names: &str,
+    header_col_types: &str,
+    meta_url: &str,
+    #[default = "1L"] n_threads: i32,
+    #[default = "'POSIXct'"] timestamp_class: &str,
+    #[default = "''"] tz: &str,
+    #[default = "'earliest'"] ambiguous: &str,
+    #[default = "FALSE"] naive_floating: bool,
+    #[default = "NULL"] date_cols: Robj,
+    #[default = "'sf'"] geometry_class: &str,
+    #[default = "FALSE"] validate_geometry: bool,
+    #[default = "FALSE"] keep_extra_fields: bool,
+) -> Robj {
+    into_result_robj(|| {
+        let options = ReadOptions::from_args(
+            timestamp_class,
+            tz,
+            ambiguous,
+            naive_floating,
+            date_cols,
+            geometry_class,
+        )?;
+        let reader = new_reader(
+            header_col_names,
+            header_col_types,
+            meta_url,
+            options.floating,
+            validate_geometry,
+            Format::Csv,
+            keep_extra_fields,
+        )?;
+        read_pages(raw_csv, reader, n_threads)?.finish(&options.output)
+    })
+}
+
+// Parse raw Socrata GeoJSON FeatureCollection pages into a named list of R
+// columns. Each feature's `geometry` goes to the first geometry column of the
+// headers and its `properties` to the other columns. Options are handled as in
+// `parse_data_json()`.
+#[extendr]
+#[allow(clippy::too_many_arguments)]
+fn parse_data_geojson(
+    raw_geojson: List,
+    header_col_names: &str,
+    header_col_types: &str,
+    meta_url: &str,
+    #[default = "1L"] n_threads: i32,
+    #[default = "'POSIXct'"] timestamp_class: &str,
+    #[default = "''"] tz: &str,
+    #[default = "'earliest'"] ambiguous: &str,
+    #[default = "FALSE"] naive_floating: bool,
+    #[default = "NULL"] date_cols: Robj,
+    #[default = "'sf'"] geometry_class: &str,
+    #[default = "FALSE"] validate_geometry: bool,
+    #[default = "FALSE"] keep_extra_fields: bool,
+) -> Robj {
+    into_result_robj(|| {
+        let options = ReadOptions::from_args(
+            timestamp_class,
+            tz,
+            ambiguous,
+            naive_floating,
+            date_cols,
+            geometry_class,
+        )?;
+        let reader = new_reader(
+            header_col_names,
+            header_col_types,
+            meta_url,
+            options.floating,
+            validate_geometry,
+            Format::GeoJson,
+            keep_extra_fields,
+        )?;
+        read_pages(raw_geojson, reader, n_threads)?.finish(&options.output)
+    })
+}
+
+// Parse Socrata pages archived in local files into a named list of R columns.
+// Each file is one page of `format`: "json" (an array of row objects),
+// "ndjson" (one row object per line), "csv" or "geojson", optionally gzip or
+// zstd compressed. Uncompressed files are memory-mapped rather than read.
+// Errors give the position of the file in `paths` as the page. Options are
+// handled as in `parse_data_json()`.
+#[extendr]
+#[allow(clippy::too_many_arguments)]
+fn parse_data_files(
+    paths: Vec<String>,
+    header_col_names: &str,
+    header_col_types: &str,
+    meta_url: &str,
+    #[default = "'json'"] format: &str,
+    #[default = "1L"] n_threads: i32,
+    #[default = "'POSIXct'"] timestamp_class: &str,
+    #[default = "''"] tz: &str,
+    #[default = "'earliest'"] ambiguous: &str,
+    #[default = "FALSE"] naive_floating: bool,
+    #[default = "NULL"] date_cols: Robj,
+    #[default = "'sf'"] geometry_class: &str,
+    #[default = "FALSE"] validate_geometry: bool,
+    #[default = "FALSE"] keep_extra_fields: bool,
+) -> Robj {
+    into_result_robj(|| {
+        let options = ReadOptions::from_args(
+            timestamp_class,
+            tz,
+            ambiguous,
+            naive_floating,
+            date_cols,
+            geometry_class,
+        )?;
+        let reader = new_reader(
+            header_col_names,
+            header_col_types,
+            meta_url,
+            options.floating,
+            validate_geometry,
+            format_arg(format)?,
+            keep_extra_fields,
+        )?;
+        read_files(&paths, reader, n_threads)?.finish(&options.output)
+    })
+}
+
+// Parse legacy `rows.json` exports into a named list of R columns, taking the
+// schema from the `meta.view.columns` of the first file, which every other file
+// must share. System columns (`:sid`, `:position`, ...) are kept and typed by
+// name. Options are handled as in `parse_data_json()`.
+#[extendr]
+#[allow(clippy::too_many_arguments)]
+fn parse_rows_json(
+    raw_rows: List,
+    meta_url: &str,
+    #[default = "1L"] n_threads: i32,
+    #[default = "'POSIXct'"] timestamp_class: &str,
+    #[default = "''"] tz: &str,
+    #[default = "'earliest'"] ambiguous: &str,
+    #[default = "FALSE"] naive_floating: bool,
+    #[default = "NULL"] date_cols: Robj,
+    #[default = "'sf'"] geometry_class: &str,
+    #[default = "FALSE"] validate_geometry: bool,
+) -> Robj {
+    into_result_robj(|| {
+        let options = ReadOptions::from_args(
+            timestamp_class,
+            tz,
+            ambiguous,
+            naive_floating,
+            date_cols,
+            geometry_class,
+        )?;
+        let (col_names, col_types) = match raw_rows.values().next() {
+            Some(first) => {
+                let bytes = first.as_raw_slice().ok_or(ParseError::RawPage { page: 1 })?;
+                rows::rows_schema(bytes)?
+            }
+            None => (Vec::new(), Vec::new()),
+        };
+        let reader = Reader::new(
+            col_names,
+            col_types,
+            options.floating,
+            meta_url,
+            validate_geometry,
+            Format::RowsJson,
+            false,
+        );
+        read_pages(raw_rows, reader, n_threads)?.finish(&options.output)
+    })
+}
+
+// Compare two snapshots of a dataset, each a list of raw pages of `format` read
+// with the same headers, matching rows by the values of the `key` column.
+// Returns a list of the `added` rows of `new_pages`, the `deleted` rows of
+// `old_pages` and the `modified` rows of `new_pages`, each a named list of R
+// columns, and `changed`, a list of the names of the columns that differ in
+// each modified row. Values are compared as parsed, so timestamps, numbers and
+// geometries written differently but equal are not changes. Errors give the
+// page within its snapshot. Options are handled as in `parse_data_json()`.
+#[extendr]
+#[allow(clippy::too_many_arguments)]
+fn diff_data(
+    old_pages: List,
+    new_pages: List,
+    header_col_names: &str,
+    header_col_types: &str,
+    key: &str,
+    meta_url: &str,
+    #[default = "'json'"] format: &str,
+    #[default = "1L"] n_threads: i32,
+    #[default = "'POSIXct'"] timestamp_class: &str,
+    #[default = "''"] tz: &str,
+    #[default = "'earliest'"] ambiguous: &str,
+    #[default = "FALSE"] naive_floating: bool,
+    #[default = "NULL"] date_cols: Robj,
+    #[default = "'sf'"] geometry_class: &str,
+) -> Robj {
+    into_result_robj(|| {
+        let format = format_arg(format)?;
+        let options = ReadOptions::from_args(
+            timestamp_class,
+            tz,
+            ambiguous,
+            naive_floating,
+            date_cols,
+            geometry_class,
+        )?;
+        let floating = options.floating;
+        let read = |pages: List| {
+            let reader = new_reader(
+                header_col_names,
+                header_col_types,
+                meta_url,
+                floating,
+                false,
+                format,
+                false,
+            )?;
+            read_pages(pages, reader, n_threads)
+        };
+        let old = read(old_pages)?;
+        let new = read(new_pages)?;
+        let changes = diff::diff(&new.col_names, &old.columns, &new.columns, key)?;
+
+        // the subsets are converted apart, so pick the date columns from the
+        // whole snapshots for them to agree
+        let options = OutputOptions {
+            date_columns: options.output.date_columns.across(
+                &new.col_names,
+                &[&old.columns, &new.columns],
+                floating,
+            ),
+            ..options.output
+        };
+        let rows = |columns: &[Column], rows: &[usize]| {
+            let columns = columns.iter().map(|column| column.take(rows)).collect();
+            as_rlist(new.col_names.clone(), columns, floating, &options)
+        };
+        let modified: Vec<usize> = changes.modified.iter().map(|(row, _)| *row).collect();
+        let changed = List::from_values(changes.modified.iter().map(|(_, columns)| {
+            r!(columns
+                .iter()
+                .map(|&c| new.col_names[c].as_str())
+                .collect::<Vec<_>>())
+        }));
+
+        Ok(list!(
+            added = rows(&new.columns, &changes.added)?,
+            deleted = rows(&old.columns, &changes.deleted)?,
+            modified = rows(&new.columns, &modified)?,
+            changed = changed
+        )
+        .into_robj())
+    })
+}
+
+// Parse raw Socrata JSON pages and move the result, as an Arrow C stream, into
+// the `ArrowArrayStream` at `stream_addr`.
+//
+// Returns the names and types of unrecognized columns, or a
+// `socratadata_parse_error` object on failure.
+#[extendr]
+#[allow(clippy::too_many_arguments)]
+fn parse_data_arrow(
+    raw_json: List,
+    header_col_names: &str,
+    header_col_types: &str,
+    meta_url: &str,
+    stream_addr: &str,
+    #[default = "1L"] n_threads: i32,
+    #[default = "''"] tz: &str,
+    #[default = "'earliest'"] ambiguous: &str,
+    #[default = "FALSE"] naive_floating: bool,
+) -> Robj {
+    into_result_robj(|| {
+        let addr = stream_ptr(stream_addr)?;
+        let floating = floating_arg(tz, ambiguous, naive_floating)?;
+        let reader = new_reader(
+            header_col_names,
+            header_col_types,
+            meta_url,
+            floating,
+            false,
+            Format::Json,
+            false,
+        )?;
+        Ok(export_reader(
+            read_pages(raw_json, reader, n_threads)?,
+            addr,
+        ))
+    })
+}
+
+/// Run the body of an R entry point, returning its result or, if it fails, a
+/// `socratadata_parse_error` object for R to turn into a condition.
+fn into_result_robj(body: impl FnOnce() -> std::result::Result<Robj, ParseError>) -> Robj {
+    body().unwrap_or_else(ParseError::into_robj)
+}
+
+/// The conversion and floating timestamp options that the functions parsing
+/// pages into R columns share.
+struct ReadOptions {
+    output: OutputOptions,
+    floating: Floating,
+}
+
+impl ReadOptions {
+    fn from_args(
+        timestamp_class: &str,
+        tz: &str,
+        ambiguous: &str,
+        naive_floating: bool,
+        date_cols: Robj,
+        geometry_class: &str,
+    ) -> std::result::Result<ReadOptions, ParseError> {
+        Ok(ReadOptions {
+            output: output_options(timestamp_class, date_cols, geometry_class)?,
+            floating: floating_arg(tz, ambiguous, naive_floating)?,
         })
-        .collect();
+    }
+}
+
+/// Build the R conversion options from R arguments.
+fn output_options(
+    timestamp_class: &str,
+    date_cols: Robj,
+    geometry_class: &str,
+) -> std::result::Result<OutputOptions, ParseError> {
+    let timestamp_class = TimestampClass::from_name(timestamp_class).ok_or_else(|| {
+        ParseError::argument("`timestamp_class` must be \"POSIXct\" or \"nanotime\"")
+    })?;
+    let date_columns = if date_cols.is_null() {
+        DateColumns::Auto
+    } else {
+        match date_cols.as_str_vector() {
+            Some(names) => DateColumns::Named(names.into_iter().map(String::from).collect()),
+            None => {
+                return Err(ParseError::argument(
+                    "`date_cols` must be a character vector or `NULL`",
+                ))
+            }
+        }
+    };
+    let geometry_class = GeometryClass::from_name(geometry_class).ok_or_else(|| {
+        ParseError::argument("`geometry_class` must be \"sf\", \"wkb\" or \"wkt\"")
+    })?;
+
+    Ok(OutputOptions {
+        timestamp_class,
+        date_columns,
+        geometry_class,
+    })
+}
+
+/// Build the floating timestamp policy from R arguments, where an empty `tz`
+/// means the session timezone.
+fn floating_arg(
+    tz: &str,
+    ambiguous: &str,
+    naive: bool,
+) -> std::result::Result<Floating, ParseError> {
+    if naive {
+        return Ok(Floating::Naive);
+    }
+    let ambiguous = Ambiguous::from_name(ambiguous).ok_or_else(|| {
+        ParseError::argument(
+            "`ambiguous` must be one of \"earliest\", \"latest\", \"shift-forward\", \"NA\" or \"error\"",
+        )
+    })?;
+    let (tz, session) = if tz.is_empty() {
+        (session_tz(), true)
+    } else {
+        match tz.parse::<Tz>() {
+            Ok(tz) => (tz, false),
+            Err(_) => return Err(ParseError::argument(format!("Unknown timezone \"{}\"", tz))),
+        }
+    };
+    Ok(Floating::Zoned {
+        tz,
+        ambiguous,
+        session,
+    })
+}
+
+fn stream_ptr(stream_addr: &str) -> std::result::Result<*mut ArrowArrayStream, ParseError> {
+    match stream_addr.parse::<usize>() {
+        Ok(addr) if addr != 0 => Ok(addr as *mut ArrowArrayStream),
+        _ => Err(ParseError::argument(
+            "`stream_addr` must be the address of an ArrowArrayStream",
+        )),
+    }
+}
+
+/// Move the reader's columns into the stream at `addr`, returning the names
+/// and types of unrecognized columns, with those of the fields outside the
+/// schema that were kept in the `extra_fields` attribute.
+fn export_reader(mut reader: Reader, addr: *mut ArrowArrayStream) -> Robj {
+    let extra = reader.add_extra_columns();
+    let floating = reader.floating();
+    let (names, types): (Vec<String>, Vec<String>) = reader.unknown_types.into_iter().unzip();
+    let stream = as_arrow_stream(reader.col_names, reader.columns, floating);
+    unsafe { export_stream(stream, addr) };
+
+    let mut unknown = r!(types);
+    unknown.set_names(names).unwrap();
+    if !extra.is_empty() {
+        let (names, types): (Vec<String>, Vec<String>) = extra.into_iter().unzip();
+        let mut extra = r!(types);
+        extra.set_names(names).unwrap();
+        unknown.set_attrib("extra_fields", extra).unwrap();
+    }
+    unknown
+}
+
+/// Build the format argument from R.
+fn format_arg(format: &str) -> std::result::Result<Format, ParseError> {
+    Format::from_name(format).ok_or_else(|| {
+        ParseError::argument("`format` must be \"json\", \"ndjson\", \"csv\" or \"geojson\"")
+    })
+}
+
+/// Create a reader for the `X-SODA2-Fields`/`X-SODA2-Types` headers.
+fn new_reader(
+    header_col_names: &str,
+    header_col_types: &str,
+    meta_url: &str,
+    floating: Floating,
+    repair: bool,
+    format: Format,
+    extra_fields: bool,
+) -> std::result::Result<Reader, ParseError> {
+    let col_names = parse_header("X-SODA2-Fields", header_col_names)?;
+    let col_types = parse_header("X-SODA2-Types", header_col_types)?;
+    Ok(Reader::new(
+        col_names,
+        col_types,
+        floating,
+        meta_url,
+        repair,
+        format,
+        extra_fields,
+    ))
+}
 
-    let mut columns: Vec<Column> = col_types
+fn read_pages(
+    raw_pages: List,
+    mut reader: Reader,
+    n_threads: i32,
+) -> std::result::Result<Reader, ParseError> {
+    // R memory can only be touched from the main thread, so borrow every page
+    // body up front and hand plain byte slices to the parser threads
+    let bodies: Vec<Robj> = raw_pages.values().collect();
+    let pages = bodies
         .iter()
-        .map(|ty| match ty.as_str() {
-            "boolean" => Column::Boolean(Vec::with_capacity(rows.len())),
-            "number" => Column::Number(Vec::with_capacity(rows.len())),
-            "fixed_timestamp" => Column::FixedTimestamp(Vec::with_capacity(rows.len())),
-            "floating_timestamp" => Column::FloatingTimestamp(Vec::with_capacity(rows.len())),
-            "text" | "row_identifier" | "row_version" => Column::Text(Vec::with_capacity(rows.len())),
-            "url" => Column::Url((
-                Vec::with_capacity(rows.len()),
-                Vec::with_capacity(rows.len()),
-            )),
-            "photo" => Column::Photo(Vec::with_capacity(rows.len())),
-            "document" => Column::Document(Vec::with_capacity(rows.len())),
-            "point" => Column::Point(Vec::with_capacity(rows.len())),
-            "line" => Column::Line(Vec::with_capacity(rows.len())),
-            "polygon" => Column::Polygon(Vec::with_capacity(rows.len())),
-            "multipoint" => Column::MultiPoint(Vec::with_capacity(rows.len())),
-            "multiline" => Column::MultiLine(Vec::with_capacity(rows.len())),
-            "multipolygon" => Column::MultiPolygon(Vec::with_capacity(rows.len())),
-            "location" => Column::Location((
-                Vec::with_capacity(rows.len()),
-                Vec::with_capacity(rows.len()),
-                Vec::with_capacity(rows.len()),
-                Vec::with_capacity(rows.len()),
-                Vec::with_capacity(rows.len()),
-            )),
-            _ => unreachable!("Unsupported type"),
-        })
-        .collect();
+        .enumerate()
+        .map(|(i, robj)| robj.as_raw_slice().ok_or(ParseError::RawPage { page: i + 1 }))
+        .collect::<std::result::Result<Vec<&[u8]>, ParseError>>()?;
+
+    reader.push_pages(&pages, resolve_threads(n_threads))?;
+
+    Ok(reader)
+}
 
-    // get the R timezone so that floating timestamps are read correctly
+fn read_files(
+    paths: &[String],
+    mut reader: Reader,
+    n_threads: i32,
+) -> std::result::Result<Reader, ParseError> {
+    // open one file per thread at a time, so that no more than that many
+    // decompressed files are held in memory
+    let n_threads = resolve_threads(n_threads);
+    for batch in paths.chunks(n_threads) {
+        let files = batch
+            .iter()
+            .map(|path| PageFile::open(path))
+            .collect::<std::result::Result<Vec<PageFile>, ParseError>>()?;
+        let pages: Vec<&[u8]> = files.iter().map(|file| &file[..]).collect();
+        reader.push_pages(&pages, n_threads)?;
+    }
+
+    Ok(reader)
+}
+
+/// Get the R timezone so that floating timestamps are read correctly.
+fn session_tz() -> Tz {
     let tz_str = R!("Sys.timezone()")
         .ok()
         .and_then(|robj| robj.as_str().map(|s| s.to_string()))
         .filter(|s| !s.is_empty())
         .unwrap_or_else(|| "UTC".to_string());
-    let tz: Tz = tz_str.parse().unwrap_or(chrono_tz::UTC);
-
-    for row in &rows {
-        for (i, (col_name, _col_type)) in col_names.iter().zip(col_types.iter()).enumerate() {
-            let val = row.get(col_name);
-            match &mut columns[i] {
-                Column::Boolean(vec) => {
-                    vec.push(parse_boolean(val));
-                }
-                Column::Number(vec) => {
-                    vec.push(parse_number(val));
-                }
-                Column::FixedTimestamp(vec) => {
-                    vec.push(parse_fixed_timestamp(val));
-                }
-                Column::FloatingTimestamp(vec) => {
-                    vec.push(parse_floating_timestamp(val, tz));
-                }
-                Column::Text(vec) => {
-                    vec.push(parse_text(val));
-                }
-                Column::Url((urls, descs)) => {
-                    let (url_val, desc_val) = parse_url(val);
-                    urls.push(url_val);
-                    descs.push(desc_val);
-                }
-                Column::Photo(vec) => {
-                    vec.push(parse_photo(val, meta_url));
-                }
-                Column::Document(vec) => {
-                    vec.push(parse_document(val, meta_url));
-                }
-                Column::Point(vec) => {
-                    vec.push(parse_point(val));
-                }
-                Column::Line(vec) => {
-                    vec.push(parse_line(val));
-                }
-                Column::Polygon(vec) => {
-                    vec.push(parse_polygon(val));
-                }
-                Column::MultiPoint(vec) => {
-                    vec.push(parse_multipoint(val));
-                }
-                Column::MultiLine(vec) => {
-                    vec.push(parse_multiline(val));
+    tz_str.parse().unwrap_or(chrono_tz::UTC)
+}
+
+/// Resolve a requested thread count, where values below one mean "use every
+/// available core".
+fn resolve_threads(n_threads: i32) -> usize {
+    if n_threads >= 1 {
+        n_threads as usize
+    } else {
+        std::thread::available_parallelism().map_or(1, |n| n.get())
+    }
+}
+
+fn parse_header(header: &str, value: &str) -> std::result::Result<Vec<String>, ParseError> {
+    serde_json::from_str(value).map_err(|err| ParseError::Header {
+        header: header.to_string(),
+        message: err.to_string(),
+    })
+}
+
+// Create a `SocrataReader` for a set of headers, or return a
+// `socratadata_parse_error` object if the headers are invalid. Floating
+// timestamps, `validate_geometry` and `keep_extra_fields` are handled as in
+// `parse_data_json()`, and `format` is "json", "ndjson", "csv" or "geojson".
+#[extendr]
+#[allow(clippy::too_many_arguments)]
+fn socrata_reader(
+    header_col_names: &str,
+    header_col_types: &str,
+    meta_url: &str,
+    #[default = "1L"] n_threads: i32,
+    #[default = "''"] tz: &str,
+    #[default = "'earliest'"] ambiguous: &str,
+    #[default = "FALSE"] naive_floating: bool,
+    #[default = "FALSE"] validate_geometry: bool,
+    #[default = "'json'"] format: &str,
+    #[default = "FALSE"] keep_extra_fields: bool,
+) -> Robj {
+    into_result_robj(|| {
+        let reader = new_reader(
+            header_col_names,
+            header_col_types,
+            meta_url,
+            floating_arg(tz, ambiguous, naive_floating)?,
+            validate_geometry,
+            format_arg(format)?,
+            keep_extra_fields,
+        )?;
+        Ok(SocrataReader {
+            pool: Some(PagePool::new(reader, resolve_threads(n_threads))),
+        }
+        .into())
+    })
+}
+
+// Parses Socrata pages as they are downloaded, so that each response can be
+// dropped as soon as it has been pushed.
+#[extendr]
+pub struct SocrataReader {
+    pool: Option<PagePool>,
+}
+
+impl SocrataReader {
+    fn pool(&mut self) -> std::result::Result<&mut PagePool, ParseError> {
+        self.pool.as_mut().ok_or_else(finished_reader)
+    }
+
+    fn take_reader(&mut self) -> std::result::Result<Reader, ParseError> {
+        self.pool.take().ok_or_else(finished_reader)?.finish()
+    }
+}
+
+#[extendr]
+impl SocrataReader {
+    // Queue a raw page for parsing. Returns `NULL`, or a
+    // `socratadata_parse_error` object if any page parsed so far failed.
+    fn push_page(&mut self, raw_json: Robj) -> Robj {
+        into_result_robj(|| {
+            let pool = self.pool()?;
+            match raw_json.as_raw_slice() {
+                Some(bytes) => pool.push_page(bytes)?,
+                None => {
+                    return Err(ParseError::RawPage {
+                        page: pool.n_pages() + 1,
+                    })
                 }
-                Column::MultiPolygon(vec) => {
-                    vec.push(parse_multipolygon(val));
+            }
+            Ok(r!(NULL))
+        })
+    }
+
+    // Number of rows in the pages pushed so far.
+    fn n_rows(&mut self) -> Robj {
+        into_result_robj(|| Ok(r!(self.pool()?.n_rows()? as i32)))
+    }
+
+    // Socrata datatypes of the columns, named by field.
+    fn schema(&mut self) -> Robj {
+        into_result_robj(|| {
+            let reader = self.pool()?.reader();
+            let mut schema = r!(reader.col_types.clone());
+            schema.set_names(reader.col_names.clone()).unwrap();
+            Ok(schema)
+        })
+    }
+
+    // Return the parsed columns as a named list, as `parse_data_json()` does.
+    fn finish(
+        &mut self,
+        #[default = "'POSIXct'"] timestamp_class: &str,
+        #[default = "NULL"] date_cols: Robj,
+        #[default = "'sf'"] geometry_class: &str,
+    ) -> Robj {
+        into_result_robj(|| {
+            let options = output_options(timestamp_class, date_cols, geometry_class)?;
+            self.take_reader()?.finish(&options)
+        })
+    }
+
+    // Move the parsed columns into the `ArrowArrayStream` at `stream_addr`, as
+    // `parse_data_arrow()` does.
+    fn finish_arrow(&mut self, stream_addr: &str) -> Robj {
+        into_result_robj(|| {
+            let addr = stream_ptr(stream_addr)?;
+            Ok(export_reader(self.take_reader()?, addr))
+        })
+    }
+}
+
+fn finished_reader() -> ParseError {
+    ParseError::argument("The reader has already been finished")
+}
+
+// Create a `ParquetSink` writing to `path`, or return a
+// `socratadata_parse_error` object if the headers are invalid or the file
+// cannot be created.
+#[extendr]
+fn parquet_sink(
+    path: &str,
+    header_col_names: &str,
+    header_col_types: &str,
+    meta_url: &str,
+) -> Robj {
+    into_result_robj(|| {
+        ParquetSink::create(path, header_col_names, header_col_types, meta_url).map(Robj::from)
+    })
+}
+
+// Writes Socrata pages to a GeoParquet file as they arrive, one row group per
+// page, so that a dataset never has to fit in memory.
+#[extendr]
+pub struct ParquetSink {
+    path: String,
+    reader: Reader,
+    writer: Option<ParquetWriter>,
+    unknown_types: Robj,
+}
+
+impl ParquetSink {
+    fn create(
+        path: &str,
+        header_col_names: &str,
+        header_col_types: &str,
+        meta_url: &str,
+    ) -> std::result::Result<ParquetSink, ParseError> {
+        let col_names = parse_header("X-SODA2-Fields", header_col_names)?;
+        let col_types = parse_header("X-SODA2-Types", header_col_types)?;
+
+        // Parquet timestamps without a zone hold wall-clock times
+        let reader = Reader::new(
+            col_names,
+            col_types,
+            Floating::Naive,
+            meta_url,
+            false,
+            Format::Json,
+            false,
+        );
+        let writer = ParquetWriter::create(path, &reader.col_names, &reader.columns)
+            .map_err(|err| io_error(path, err))?;
+
+        let (names, types): (Vec<String>, Vec<String>) =
+            reader.unknown_types.iter().cloned().unzip();
+        let mut unknown_types = r!(types);
+        unknown_types.set_names(names).unwrap();
+
+        Ok(ParquetSink {
+            path: path.to_string(),
+            reader,
+            writer: Some(writer),
+            unknown_types,
+        })
+    }
+
+    fn writer(&mut self) -> std::result::Result<&mut ParquetWriter, ParseError> {
+        self.writer.as_mut().ok_or_else(finished_sink)
+    }
+}
+
+#[extendr]
+impl ParquetSink {
+    // Parse a raw page and append it as a row group. Returns `NULL`, or a
+    // `socratadata_parse_error` object on failure.
+    fn write_page(&mut self, raw_json: Robj) -> Robj {
+        into_result_robj(|| {
+            let page = self.reader.n_pages() + 1;
+            let result = match raw_json.as_raw_slice() {
+                Some(bytes) => self.reader.push_page(bytes),
+                None => Err(ParseError::RawPage { page }),
+            };
+            if let Err(err) = result {
+                // drop any rows of the failed page
+                self.reader.take_chunk();
+                return Err(err);
+            }
+
+            let columns = self.reader.take_chunk().columns;
+            let path = self.path.clone();
+            self.writer()?
+                .write_row_group(&columns)
+                .map_err(|err| io_error(&path, err))?;
+            Ok(r!(NULL))
+        })
+    }
+
+    // Names and types of unrecognized columns, which are written as text.
+    fn unknown_types(&self) -> Robj {
+        self.unknown_types.clone()
+    }
+
+    // Write the Parquet footer and close the file. Returns `NULL`, or a
+    // `socratadata_parse_error` object on failure.
+    fn finish(&mut self) -> Robj {
+        into_result_robj(|| {
+            let writer = self.writer.take().ok_or_else(finished_sink)?;
+            writer.finish().map_err(|err| io_error(&self.path, err))?;
+            Ok(r!(NULL))
+        })
+    }
+}
+
+fn finished_sink() -> ParseError {
+    ParseError::argument("The Parquet file has already been finished")
+}
+
+fn io_error(path: &str, err: std::io::Error) -> ParseError {
+    ParseError::Io {
+        path: path.to_string(),
+        message: err.to_string(),
+    }
+}
+
+// Resolve the field names and types of a dataset from the
+// `X-SODA2-Fields`/`X-SODA2-Types` headers and the first `n_rows` rows of a
+// page.
+//
+// Missing headers (`NULL`) are inferred from the data, and header types that
+// do not fit the data are replaced by the inferred type. The page is read as
+// `format`, "json", "ndjson", "csv" or "geojson". Returns a list with
+// the headers as JSON strings and `inferred`, a named character vector of the
+// columns whose type was inferred, or a `socratadata_parse_error` object.
+#[extendr]
+fn resolve_headers(
+    raw_json: Robj,
+    header_col_names: Robj,
+    header_col_types: Robj,
+    #[default = "1000L"] n_rows: i32,
+    #[default = "'json'"] format: &str,
+) -> Robj {
+    let header = |name: &str, robj: &Robj| match robj.as_str() {
+        Some(value) => parse_header(name, value).map(Some),
+        None => Ok(None),
+    };
+
+    into_result_robj(|| {
+        let bytes = raw_json
+            .as_raw_slice()
+            .ok_or(ParseError::RawPage { page: 1 })?;
+        let format = format_arg(format)?;
+        let names = header("X-SODA2-Fields", &header_col_names)?;
+        let types = header("X-SODA2-Types", &header_col_types)?;
+        let n_rows = n_rows.max(0) as usize;
+        let sample = match format {
+            Format::Json => infer::infer_schema(bytes, n_rows)?,
+            Format::Csv => infer::infer_csv_schema(bytes, n_rows)?,
+            Format::GeoJson => infer::infer_geojson_schema(bytes, n_rows)?,
+            Format::Ndjson => infer::infer_ndjson_schema(bytes, n_rows)?,
+            // rows.json files carry their own schema
+            Format::RowsJson => Vec::new(),
+        };
+        let (names, types, inferred) = resolve_schema(names, types, sample);
+
+        let (inferred_names, inferred_types): (Vec<String>, Vec<String>) =
+            inferred.into_iter().unzip();
+        let mut inferred = r!(inferred_types);
+        inferred.set_names(inferred_names).unwrap();
+
+        Ok(list!(
+            header_col_names = serde_json::to_string(&names).unwrap(),
+            header_col_types = serde_json::to_string(&types).unwrap(),
+            inferred = inferred
+        )
+        .into_robj())
+    })
+}
+
+/// Combine optional header names and types with an inferred schema,
+/// returning the names, the types and the `(name, type)` pairs that were
+/// inferred rather than taken from the headers.
+fn resolve_schema(
+    names: Option<Vec<String>>,
+    types: Option<Vec<String>>,
+    sample: infer::InferredSchema,
+) -> (Vec<String>, Vec<String>, Vec<(String, String)>) {
+    let sampled: std::collections::HashMap<&str, &infer::SampledField> = sample
+        .iter()
+        .map(|field| (field.name.as_str(), field))
+        .collect();
+    // types are given by position, so they mean nothing without the names
+    let (names, types) = match names {
+        Some(names) => (names, types),
+        None => (
+            sample.iter().map(|field| field.name.clone()).collect(),
+            None,
+        ),
+    };
+
+    let mut inferred = Vec::new();
+    let types = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let sampled = sampled.get(name.as_str()).copied();
+            let values = sampled.map_or(&[][..], |field| field.values.as_slice());
+            let header = types.as_ref().and_then(|types| types.get(i));
+            if let Some(ty) = infer::system_type(name) {
+                return ty.to_string();
+            }
+            match (header, sampled.and_then(|field| field.ty)) {
+                (Some(header), Some(ty))
+                    if header != ty && !infer::is_compatible(header, values) =>
+                {
+                    inferred.push((name.clone(), ty.to_string()));
+                    ty.to_string()
                 }
-                Column::Location((coords, addresses, cities, states, zips)) => {
-                    let (latlon, addr, city, state, zip) = parse_location(val);
-                    coords.push(latlon);
-                    addresses.push(addr);
-                    cities.push(city);
-                    states.push(state);
-                    zips.push(zip);
+                (Some(header), _) => header.clone(),
+                (None, ty) => {
+                    let ty = ty.unwrap_or("text").to_string();
+                    inferred.push((name.clone(), ty.clone()));
+                    ty
                 }
             }
-        }
-    }
+        })
+        .collect();
 
-    as_rlist(col_names, columns)
+    (names, types, inferred)
 }
 
 #[extendr]
 fn is_empty_raw_json(raw_json: Robj) -> bool {
-    let bytes = raw_json.as_raw_slice().unwrap();
+    let bytes = match raw_json.as_raw_slice() {
+        Some(bytes) => bytes,
+        None => return false,
+    };
 
     match serde_json::from_slice::<Value>(bytes) {
         Ok(Value::Array(arr)) => arr.is_empty(),
@@ -143,11 +953,36 @@ fn is_empty_raw_json(raw_json: Robj) -> bool {
     }
 }
 
+#[extendr]
+fn is_empty_raw_csv(raw_csv: Robj) -> bool {
+    raw_csv.as_raw_slice().map_or(false, infer::is_empty_csv)
+}
+
+#[extendr]
+fn is_empty_raw_geojson(raw_geojson: Robj) -> bool {
+    raw_geojson
+        .as_raw_slice()
+        .map_or(false, infer::is_empty_geojson)
+}
+
 // Macro to generate exports.
 // This ensures exported functions are registered with R.
 // See corresponding C code in `entrypoint.c`.
 extendr_module! {
     mod socratadata;
     fn parse_data_json;
+    fn parse_data_csv;
+    fn parse_data_geojson;
+    fn parse_rows_json;
+    fn parse_data_files;
+    fn diff_data;
+    fn parse_data_arrow;
     fn is_empty_raw_json;
+    fn is_empty_raw_csv;
+    fn is_empty_raw_geojson;
+    fn resolve_headers;
+    fn socrata_reader;
+    fn parquet_sink;
+    impl SocrataReader;
+    impl ParquetSink;
 }
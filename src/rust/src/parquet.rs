@@ -0,0 +1,655 @@
+//! A minimal streaming Parquet / GeoParquet writer.
+//!
+//! Every Socrata column is written as one or more flat `OPTIONAL` leaf
+//! columns using PLAIN encoding without compression. Each call to
+//! [`ParquetWriter::write_row_group`] appends one row group containing a
+//! single data page per column, so only the current page is ever held in
+//! memory. The footer is written by [`ParquetWriter::finish`].
+//!
+//! See <https://parquet.apache.org/docs/file-format/> and
+//! <https://geoparquet.org/releases/v1.1.0/>.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::geom::Dim;
+use crate::process::Column;
+use crate::wkb;
+
+const MAGIC: &[u8] = b"PAR1";
+
+// parquet.thrift enum values
+const TYPE_BOOLEAN: i32 = 0;
+const TYPE_INT64: i32 = 2;
+const TYPE_DOUBLE: i32 = 5;
+const TYPE_BYTE_ARRAY: i32 = 6;
+const REPETITION_OPTIONAL: i32 = 1;
+const REPETITION_REQUIRED: i32 = 0;
+const CONVERTED_UTF8: i32 = 0;
+const CONVERTED_TIMESTAMP_MICROS: i32 = 10;
+const CONVERTED_JSON: i32 = 19;
+const ENCODING_PLAIN: i32 = 0;
+const ENCODING_RLE: i32 = 3;
+const CODEC_UNCOMPRESSED: i32 = 0;
+const PAGE_DATA: i32 = 0;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Logical {
+    None,
+    String,
+    Json,
+    Timestamp { utc: bool },
+}
+
+/// Schema of a single leaf column.
+struct Leaf {
+    name: String,
+    physical: i32,
+    logical: Logical,
+    geometry: Option<&'static str>,
+    // dimensions of the geometries written so far
+    dims: Vec<Dim>,
+}
+
+/// Values of a single leaf column for one row group.
+struct LeafData {
+    present: Vec<bool>,
+    values: Vec<u8>,
+    // dimension of a geometry leaf with at least one value
+    dim: Option<Dim>,
+}
+
+impl LeafData {
+    fn new() -> LeafData {
+        LeafData {
+            present: Vec::new(),
+            values: Vec::new(),
+            dim: None,
+        }
+    }
+
+    fn push_f64(&mut self, val: Option<f64>) {
+        self.present.push(val.is_some());
+        if let Some(x) = val {
+            self.values.extend_from_slice(&x.to_le_bytes());
+        }
+    }
+
+    fn push_i64(&mut self, val: Option<i64>) {
+        self.present.push(val.is_some());
+        if let Some(x) = val {
+            self.values.extend_from_slice(&x.to_le_bytes());
+        }
+    }
+
+    fn push_bytes(&mut self, val: Option<&[u8]>) {
+        self.present.push(val.is_some());
+        if let Some(bytes) = val {
+            self.values
+                .extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+            self.values.extend_from_slice(bytes);
+        }
+    }
+}
+
+struct ColumnChunkMeta {
+    leaf: usize,
+    num_values: i64,
+    size: i64,
+    offset: i64,
+}
+
+struct RowGroupMeta {
+    columns: Vec<ColumnChunkMeta>,
+    num_rows: i64,
+}
+
+/// Streaming writer for a Parquet file with a fixed schema.
+pub struct ParquetWriter {
+    file: BufWriter<File>,
+    offset: i64,
+    leaves: Vec<Leaf>,
+    row_groups: Vec<RowGroupMeta>,
+}
+
+impl ParquetWriter {
+    /// Create the file at `path`, taking the schema from a set of (empty)
+    /// columns.
+    pub fn create(
+        path: &str,
+        col_names: &[String],
+        columns: &[Column],
+    ) -> io::Result<ParquetWriter> {
+        let leaves = col_names
+            .iter()
+            .zip(columns.iter())
+            .flat_map(|(name, column)| leaf_schema(name, column))
+            .collect();
+
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+
+        Ok(ParquetWriter {
+            file,
+            offset: MAGIC.len() as i64,
+            leaves,
+            row_groups: Vec::new(),
+        })
+    }
+
+    /// Append the columns as a new row group. Empty pages are skipped.
+    pub fn write_row_group(&mut self, columns: &[Column]) -> io::Result<()> {
+        let num_rows = columns.first().map_or(0, Column::len);
+        if num_rows == 0 {
+            return Ok(());
+        }
+
+        let data: Vec<LeafData> = columns.iter().flat_map(leaf_data).collect();
+
+        let mut chunks = Vec::with_capacity(data.len());
+        for (leaf, leaf_data) in data.into_iter().enumerate() {
+            if let Some(dim) = leaf_data.dim {
+                let dims = &mut self.leaves[leaf].dims;
+                if !dims.contains(&dim) {
+                    dims.push(dim);
+                }
+            }
+            let offset = self.offset;
+            let size = self.write_page(leaf_data)?;
+            chunks.push(ColumnChunkMeta {
+                leaf,
+                num_values: num_rows as i64,
+                size,
+                offset,
+            });
+        }
+
+        self.row_groups.push(RowGroupMeta {
+            columns: chunks,
+            num_rows: num_rows as i64,
+        });
+        Ok(())
+    }
+
+    /// Write the file footer and flush the file.
+    pub fn finish(mut self) -> io::Result<()> {
+        let footer = self.file_metadata();
+        self.file.write_all(&footer)?;
+        self.file.write_all(&(footer.len() as u32).to_le_bytes())?;
+        self.file.write_all(MAGIC)?;
+        self.file.flush()
+    }
+
+    /// Write one data page, returning the number of bytes written.
+    fn write_page(&mut self, data: LeafData) -> io::Result<i64> {
+        let levels = encode_levels(&data.present);
+
+        let mut body = Vec::with_capacity(4 + levels.len() + data.values.len());
+        body.extend_from_slice(&(levels.len() as u32).to_le_bytes());
+        body.extend_from_slice(&levels);
+        body.extend_from_slice(&data.values);
+
+        let mut header = Thrift::new();
+        header.i32_field(1, PAGE_DATA);
+        header.i32_field(2, body.len() as i32);
+        header.i32_field(3, body.len() as i32);
+        header.begin_struct(5);
+        header.i32_field(1, data.present.len() as i32);
+        header.i32_field(2, ENCODING_PLAIN);
+        header.i32_field(3, ENCODING_RLE);
+        header.i32_field(4, ENCODING_RLE);
+        header.end_struct();
+        header.stop();
+
+        self.file.write_all(&header.buf)?;
+        self.file.write_all(&body)?;
+
+        let size = (header.buf.len() + body.len()) as i64;
+        self.offset += size;
+        Ok(size)
+    }
+
+    fn file_metadata(&self) -> Vec<u8> {
+        let mut t = Thrift::new();
+        t.i32_field(1, 1);
+
+        // schema: a required root group followed by the leaves
+        t.list_field(2, STRUCT, self.leaves.len() + 1);
+        t.begin_element();
+        t.i32_field(3, REPETITION_REQUIRED);
+        t.binary_field(4, b"schema");
+        t.i32_field(5, self.leaves.len() as i32);
+        t.end_element();
+        for leaf in &self.leaves {
+            t.begin_element();
+            t.i32_field(1, leaf.physical);
+            t.i32_field(3, REPETITION_OPTIONAL);
+            t.binary_field(4, leaf.name.as_bytes());
+            match leaf.logical {
+                Logical::None => {}
+                Logical::String => {
+                    t.i32_field(6, CONVERTED_UTF8);
+                    t.begin_struct(10);
+                    t.empty_struct_field(1);
+                    t.end_struct();
+                }
+                Logical::Json => {
+                    t.i32_field(6, CONVERTED_JSON);
+                    t.begin_struct(10);
+                    t.empty_struct_field(12);
+                    t.end_struct();
+                }
+                Logical::Timestamp { utc } => {
+                    if utc {
+                        t.i32_field(6, CONVERTED_TIMESTAMP_MICROS);
+                    }
+                    t.begin_struct(10);
+                    t.begin_struct(8);
+                    t.bool_field(1, utc);
+                    t.begin_struct(2);
+                    t.empty_struct_field(2);
+                    t.end_struct();
+                    t.end_struct();
+                    t.end_struct();
+                }
+            }
+            t.end_element();
+        }
+
+        let num_rows: i64 = self.row_groups.iter().map(|rg| rg.num_rows).sum();
+        t.i64_field(3, num_rows);
+
+        t.list_field(4, STRUCT, self.row_groups.len());
+        for rg in &self.row_groups {
+            t.begin_element();
+            t.list_field(1, STRUCT, rg.columns.len());
+            for chunk in &rg.columns {
+                let leaf = &self.leaves[chunk.leaf];
+                t.begin_element();
+                t.i64_field(2, chunk.offset);
+                t.begin_struct(3);
+                t.i32_field(1, leaf.physical);
+                t.list_field(2, I32, 2);
+                t.i32_element(ENCODING_PLAIN);
+                t.i32_element(ENCODING_RLE);
+                t.list_field(3, BINARY, 1);
+                t.binary_element(leaf.name.as_bytes());
+                t.i32_field(4, CODEC_UNCOMPRESSED);
+                t.i64_field(5, chunk.num_values);
+                t.i64_field(6, chunk.size);
+                t.i64_field(7, chunk.size);
+                t.i64_field(9, chunk.offset);
+                t.end_struct();
+                t.end_element();
+            }
+            t.i64_field(2, rg.columns.iter().map(|c| c.size).sum());
+            t.i64_field(3, rg.num_rows);
+            t.end_element();
+        }
+
+        if let Some(geo) = self.geo_metadata() {
+            t.list_field(5, STRUCT, 1);
+            t.begin_element();
+            t.binary_field(1, b"geo");
+            t.binary_field(2, geo.as_bytes());
+            t.end_element();
+        }
+
+        t.binary_field(6, b"socratadata");
+        t.stop();
+        t.buf
+    }
+
+    /// GeoParquet `geo` metadata describing every WKB geometry column.
+    fn geo_metadata(&self) -> Option<String> {
+        let geometry_leaves: Vec<(&Leaf, &str)> = self
+            .leaves
+            .iter()
+            .filter_map(|leaf| leaf.geometry.map(|ty| (leaf, ty)))
+            .collect();
+        let primary = geometry_leaves.first()?.0.name.as_str();
+
+        let mut columns = serde_json::Map::new();
+        for (leaf, ty) in &geometry_leaves {
+            columns.insert(
+                leaf.name.clone(),
+                serde_json::json!({
+                    "encoding": "WKB",
+                    "geometry_types": geometry_types(ty, &leaf.dims),
+                }),
+            );
+        }
+
+        Some(
+            serde_json::json!({
+                "version": "1.1.0",
+                "primary_column": primary,
+                "columns": columns,
+            })
+            .to_string(),
+        )
+    }
+}
+
+/// GeoParquet geometry types for the dimensions written, e.g. `Point Z`.
+/// GeoParquet has no types with m, so those are left unspecified (empty).
+fn geometry_types(ty: &str, dims: &[Dim]) -> Vec<String> {
+    if dims.iter().any(|dim| dim.m) {
+        return Vec::new();
+    }
+    if dims.is_empty() {
+        return vec![ty.to_string()];
+    }
+    dims.iter()
+        .map(|dim| match dim.z {
+            true => format!("{} Z", ty),
+            false => ty.to_string(),
+        })
+        .collect()
+}
+
+fn leaf(name: &str, physical: i32, logical: Logical) -> Leaf {
+    Leaf {
+        name: name.to_string(),
+        physical,
+        logical,
+        geometry: None,
+        dims: Vec::new(),
+    }
+}
+
+fn geometry_leaf(name: &str, geometry: &'static str) -> Leaf {
+    Leaf {
+        name: name.to_string(),
+        physical: TYPE_BYTE_ARRAY,
+        logical: Logical::None,
+        geometry: Some(geometry),
+        dims: Vec::new(),
+    }
+}
+
+/// Leaf columns for a Socrata column. Struct-like columns (url, phone and
+/// location) are flattened into `name.field` leaves.
+fn leaf_schema(name: &str, column: &Column) -> Vec<Leaf> {
+    let nested = |field: &str| format!("{}.{}", name, field);
+    match column {
+        Column::Boolean(_) | Column::Checkbox(_) => {
+            vec![leaf(name, TYPE_BOOLEAN, Logical::None)]
+        }
+        Column::Number(_) | Column::Money(_) | Column::Percent(_) => {
+            vec![leaf(name, TYPE_DOUBLE, Logical::None)]
+        }
+        Column::FixedTimestamp(_) | Column::Date(_) => {
+            vec![leaf(name, TYPE_INT64, Logical::Timestamp { utc: true })]
+        }
+        Column::FloatingTimestamp(_) | Column::CalendarDate(_) => {
+            vec![leaf(name, TYPE_INT64, Logical::Timestamp { utc: false })]
+        }
+        Column::Json(_) => vec![leaf(name, TYPE_BYTE_ARRAY, Logical::Json)],
+        Column::Text(_)
+        | Column::RowId(_)
+        | Column::Photo(_)
+        | Column::Document(_)
+        | Column::Html(_)
+        | Column::Email(_)
+        | Column::Blob(_) => vec![leaf(name, TYPE_BYTE_ARRAY, Logical::String)],
+        Column::Url(_) => vec![
+            leaf(&nested("url"), TYPE_BYTE_ARRAY, Logical::String),
+            leaf(&nested("description"), TYPE_BYTE_ARRAY, Logical::String),
+        ],
+        Column::Phone(_) => vec![
+            leaf(&nested("phone_number"), TYPE_BYTE_ARRAY, Logical::String),
+            leaf(&nested("phone_type"), TYPE_BYTE_ARRAY, Logical::String),
+        ],
+        Column::Point(_) => vec![geometry_leaf(name, "Point")],
+        Column::Line(_) => vec![geometry_leaf(name, "LineString")],
+        Column::Polygon(_) => vec![geometry_leaf(name, "Polygon")],
+        Column::MultiPoint(_) => vec![geometry_leaf(name, "MultiPoint")],
+        Column::MultiLine(_) => vec![geometry_leaf(name, "MultiLineString")],
+        Column::MultiPolygon(_) => vec![geometry_leaf(name, "MultiPolygon")],
+        Column::Location(_) => vec![
+            geometry_leaf(&nested("geometry"), "Point"),
+            leaf(&nested("address"), TYPE_BYTE_ARRAY, Logical::String),
+            leaf(&nested("city"), TYPE_BYTE_ARRAY, Logical::String),
+            leaf(&nested("state"), TYPE_BYTE_ARRAY, Logical::String),
+            leaf(&nested("zip"), TYPE_BYTE_ARRAY, Logical::String),
+        ],
+    }
+}
+
+/// Encode a column's values in the same leaf order as [`leaf_schema`].
+fn leaf_data(column: &Column) -> Vec<LeafData> {
+    let dim = column.dim();
+    match column {
+        Column::Boolean(values) | Column::Checkbox(values) => {
+            let mut data = LeafData::new();
+            let bits: Vec<bool> = values.iter().flatten().copied().collect();
+            data.present = values.iter().map(Option::is_some).collect();
+            data.values = vec![0u8; (bits.len() + 7) / 8];
+            for (i, bit) in bits.iter().enumerate() {
+                if *bit {
+                    data.values[i / 8] |= 1 << (i % 8);
+                }
+            }
+            vec![data]
+        }
+        Column::Number(values) | Column::Money(values) | Column::Percent(values) => {
+            let mut data = LeafData::new();
+            values.iter().for_each(|v| data.push_f64(*v));
+            vec![data]
+        }
+        // floating timestamps are read as naive wall-clock times, so both
+        // kinds are stored as microseconds
+        Column::FixedTimestamp(values)
+        | Column::Date(values)
+        | Column::FloatingTimestamp(values)
+        | Column::CalendarDate(values) => {
+            let mut data = LeafData::new();
+            values
+                .iter()
+                .for_each(|v| data.push_i64(v.map(|instant| instant.timestamp_micros())));
+            vec![data]
+        }
+        Column::Text(values)
+        | Column::RowId(values)
+        | Column::Photo(values)
+        | Column::Document(values)
+        | Column::Html(values)
+        | Column::Email(values)
+        | Column::Blob(values)
+        | Column::Json(values) => vec![string_data(values)],
+        Column::Url((a, b)) | Column::Phone((a, b)) => vec![string_data(a), string_data(b)],
+        Column::Point(values) => vec![wkb_data(values, dim, |out, pt, dim| {
+            wkb::write_point(out, *pt, dim)
+        })],
+        Column::Line(values) => vec![wkb_data(values, dim, |out, l, dim| {
+            wkb::write_linestring(out, l, dim)
+        })],
+        Column::Polygon(values) => vec![wkb_data(values, dim, |out, p, dim| {
+            wkb::write_polygon(out, p, dim)
+        })],
+        Column::MultiPoint(values) => {
+            vec![wkb_data(values, dim, |out, mp, dim| {
+                wkb::write_multipoint(out, mp, dim)
+            })]
+        }
+        Column::MultiLine(values) => {
+            vec![wkb_data(values, dim, |out, ml, dim| {
+                wkb::write_multilinestring(out, ml, dim)
+            })]
+        }
+        Column::MultiPolygon(values) => {
+            vec![wkb_data(values, dim, |out, mp, dim| {
+                wkb::write_multipolygon(out, mp, dim)
+            })]
+        }
+        Column::Location(location) => vec![
+            wkb_data(&location.coords, dim, |out, pt, dim| {
+                wkb::write_point(out, *pt, dim)
+            }),
+            string_data(&location.addresses),
+            string_data(&location.cities),
+            string_data(&location.states),
+            string_data(&location.zips),
+        ],
+    }
+}
+
+fn string_data(values: &[Option<String>]) -> LeafData {
+    let mut data = LeafData::new();
+    values
+        .iter()
+        .for_each(|v| data.push_bytes(v.as_deref().map(str::as_bytes)));
+    data
+}
+
+fn wkb_data<T>(values: &[Option<T>], dim: Dim, write: impl Fn(&mut Vec<u8>, &T, Dim)) -> LeafData {
+    let mut data = LeafData::new();
+    if values.iter().any(Option::is_some) {
+        data.dim = Some(dim);
+    }
+    let mut buf = Vec::new();
+    for value in values {
+        match value {
+            Some(geom) => {
+                buf.clear();
+                write(&mut buf, geom, dim);
+                data.push_bytes(Some(&buf));
+            }
+            None => data.push_bytes(None),
+        }
+    }
+    data
+}
+
+/// Encode definition levels (max level 1) with the RLE/bit-packing hybrid,
+/// using RLE runs only.
+fn encode_levels(present: &[bool]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < present.len() {
+        let value = present[i];
+        let run = present[i..].iter().take_while(|p| **p == value).count();
+        write_varint(&mut out, (run as u64) << 1);
+        out.push(value as u8);
+        i += run;
+    }
+    out
+}
+
+fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    loop {
+        if n < 0x80 {
+            out.push(n as u8);
+            return;
+        }
+        out.push((n as u8 & 0x7f) | 0x80);
+        n >>= 7;
+    }
+}
+
+// Thrift compact protocol -------------------------------------------------------
+
+const BOOL_TRUE: u8 = 1;
+const BOOL_FALSE: u8 = 2;
+const I32: u8 = 5;
+const I64: u8 = 6;
+const BINARY: u8 = 8;
+const LIST: u8 = 9;
+const STRUCT: u8 = 12;
+
+/// Just enough of the Thrift compact protocol to write Parquet metadata.
+struct Thrift {
+    buf: Vec<u8>,
+    last_field: Vec<i16>,
+}
+
+impl Thrift {
+    fn new() -> Thrift {
+        Thrift {
+            buf: Vec::new(),
+            last_field: vec![0],
+        }
+    }
+
+    fn field_header(&mut self, id: i16, ty: u8) {
+        let last = self.last_field.last_mut().unwrap();
+        let delta = id - *last;
+        if delta > 0 && delta <= 15 {
+            self.buf.push(((delta as u8) << 4) | ty);
+        } else {
+            self.buf.push(ty);
+            write_varint(&mut self.buf, zigzag(id as i64));
+        }
+        *last = id;
+    }
+
+    fn i32_field(&mut self, id: i16, val: i32) {
+        self.field_header(id, I32);
+        write_varint(&mut self.buf, zigzag(val as i64));
+    }
+
+    fn i64_field(&mut self, id: i16, val: i64) {
+        self.field_header(id, I64);
+        write_varint(&mut self.buf, zigzag(val));
+    }
+
+    fn bool_field(&mut self, id: i16, val: bool) {
+        self.field_header(id, if val { BOOL_TRUE } else { BOOL_FALSE });
+    }
+
+    fn binary_field(&mut self, id: i16, val: &[u8]) {
+        self.field_header(id, BINARY);
+        self.binary_element(val);
+    }
+
+    fn list_field(&mut self, id: i16, elem_type: u8, len: usize) {
+        self.field_header(id, LIST);
+        if len < 15 {
+            self.buf.push(((len as u8) << 4) | elem_type);
+        } else {
+            self.buf.push(0xf0 | elem_type);
+            write_varint(&mut self.buf, len as u64);
+        }
+    }
+
+    fn i32_element(&mut self, val: i32) {
+        write_varint(&mut self.buf, zigzag(val as i64));
+    }
+
+    fn binary_element(&mut self, val: &[u8]) {
+        write_varint(&mut self.buf, val.len() as u64);
+        self.buf.extend_from_slice(val);
+    }
+
+    fn begin_struct(&mut self, id: i16) {
+        self.field_header(id, STRUCT);
+        self.last_field.push(0);
+    }
+
+    fn empty_struct_field(&mut self, id: i16) {
+        self.begin_struct(id);
+        self.end_struct();
+    }
+
+    fn end_struct(&mut self) {
+        self.stop();
+        self.last_field.pop();
+    }
+
+    fn begin_element(&mut self) {
+        self.last_field.push(0);
+    }
+
+    fn end_element(&mut self) {
+        self.end_struct();
+    }
+
+    fn stop(&mut self) {
+        self.buf.push(0);
+    }
+}
+
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
@@ -1,31 +1,51 @@
-use chrono::TimeZone;
-use chrono_tz::Tz;
+use chrono::{DateTime, Utc};
 use serde_json::Value;
 
+use crate::geom::{Coord, Dim};
+use crate::time::{from_epoch_seconds, Floating};
+
 pub fn parse_boolean(val: Option<&Value>) -> Option<bool> {
-    val.and_then(Value::as_bool)
+    val.and_then(|v| match v {
+        Value::Bool(b) => Some(*b),
+        // CSV pages send booleans as text
+        Value::String(s) => match s.as_str() {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    })
 }
 pub fn parse_number(val: Option<&Value>) -> Option<f64> {
-    val.and_then(|v| v.as_str())
-        .and_then(|s| s.parse::<f64>().ok())
+    val.and_then(|v| match v {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.parse::<f64>().ok(),
+        _ => None,
+    })
 }
 
-pub fn parse_fixed_timestamp(val: Option<&Value>) -> Option<f64> {
+/// Timestamps are kept as UTC instants precise to the nanosecond, so that the
+/// fractional seconds Socrata sends are not lost, over the whole range of
+/// years it allows.
+pub fn parse_fixed_timestamp(val: Option<&Value>) -> Option<DateTime<Utc>> {
     val.and_then(Value::as_str)
-        .and_then(|s| chrono::DateTime::parse_from_rfc3339(s).ok())
-        .map(|dt| dt.to_utc().timestamp() as f64)
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|dt| dt.with_timezone(&Utc))
 }
 
-pub fn parse_floating_timestamp(val: Option<&Value>, tz: Tz) -> Option<f64> {
-    val.and_then(Value::as_str).and_then(|s| {
-        chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.3f")
-            .ok()
-            .and_then(|naive_dt| {
-                tz.from_local_datetime(&naive_dt)
-                    .single()
-                    .map(|dt| dt.timestamp() as f64)
-            })
-    })
+/// Floating timestamps are resolved according to `floating`, which fails only
+/// when a time in a daylight saving time fold or gap should be an error.
+pub fn parse_floating_timestamp(
+    val: Option<&Value>,
+    floating: Floating,
+) -> Result<Option<DateTime<Utc>>, String> {
+    match val
+        .and_then(Value::as_str)
+        .and_then(|s| chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f").ok())
+    {
+        Some(naive_dt) => floating.resolve(&naive_dt),
+        None => Ok(None),
+    }
 }
 
 pub fn parse_text(val: Option<&Value>) -> Option<String> {
@@ -33,6 +53,10 @@ pub fn parse_text(val: Option<&Value>) -> Option<String> {
 }
 
 pub fn parse_url(val: Option<&Value>) -> (Option<String>, Option<String>) {
+    if let Some(s) = val.and_then(Value::as_str) {
+        return (Some(s.to_string()), None);
+    }
+
     let url_opt = val
         .and_then(|ud| ud.get("url"))
         .and_then(|u| u.as_str())
@@ -53,7 +77,7 @@ pub fn parse_photo(val: Option<&Value>, meta_url: &str) -> Option<String> {
 
 pub fn parse_document(val: Option<&Value>, meta_url: &str) -> Option<String> {
     val.and_then(|v| v.as_object())
-        .map(|map| {
+        .and_then(|map| {
             let file_id = map.get("file_id")?.as_str()?;
             let filename = map.get("filename").and_then(|v| v.as_str()).unwrap_or("");
             let content_type = map
@@ -65,151 +89,308 @@ pub fn parse_document(val: Option<&Value>, meta_url: &str) -> Option<String> {
                 meta_url, file_id, filename, content_type
             ))
         })
-        .flatten()
 }
 
-pub fn parse_point(val: Option<&Value>) -> Option<(f64, f64)> {
-    val.and_then(|v| {
-        v.get("coordinates")
-            .and_then(|coords| coords.as_array())
-            .and_then(|arr| {
-                if arr.len() == 2 {
-                    Some((arr[0].as_f64()?, arr[1].as_f64()?))
-                } else {
-                    None
-                }
-            })
+pub fn parse_checkbox(val: Option<&Value>) -> Option<bool> {
+    val.and_then(|v| match v {
+        Value::Bool(b) => Some(*b),
+        Value::String(s) => match s.as_str() {
+            "true" | "1" => Some(true),
+            "false" | "0" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+pub fn parse_money(val: Option<&Value>) -> Option<f64> {
+    val.and_then(|v| match v {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.replace(['$', ','], "").trim().parse::<f64>().ok(),
+        _ => None,
     })
 }
 
-pub fn parse_line(val: Option<&Value>) -> Option<Vec<(f64, f64)>> {
+pub fn parse_percent(val: Option<&Value>) -> Option<f64> {
+    val.and_then(|v| match v {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().trim_end_matches('%').parse::<f64>().ok(),
+        _ => None,
+    })
+}
+
+pub fn parse_date(val: Option<&Value>) -> Option<DateTime<Utc>> {
+    val.and_then(|v| match v {
+        // legacy date columns are sometimes sent as epoch seconds
+        Value::Number(n) => n.as_f64().and_then(from_epoch_seconds),
+        Value::String(s) => DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|dt| dt.with_timezone(&Utc))
+            .or_else(|| {
+                chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f")
+                    .ok()
+                    .map(|naive_dt| naive_dt.and_utc())
+            }),
+        _ => None,
+    })
+}
+
+pub fn parse_calendar_date(
+    val: Option<&Value>,
+    floating: Floating,
+) -> Result<Option<DateTime<Utc>>, String> {
+    parse_floating_timestamp(val, floating)
+}
+
+pub fn parse_html(val: Option<&Value>) -> Option<String> {
+    parse_text(val)
+}
+
+pub fn parse_email(val: Option<&Value>) -> Option<String> {
+    parse_text(val)
+}
+
+pub fn parse_phone(val: Option<&Value>) -> (Option<String>, Option<String>) {
+    if let Some(s) = val.and_then(Value::as_str) {
+        return (Some(s.to_string()), None);
+    }
+
+    let number_opt = val
+        .and_then(|pd| pd.get("phone_number"))
+        .and_then(|n| n.as_str())
+        .map(|s| s.to_string());
+
+    let type_opt = val
+        .and_then(|pd| pd.get("phone_type"))
+        .and_then(|t| t.as_str())
+        .map(|s| s.to_string());
+
+    (number_opt, type_opt)
+}
+
+pub fn parse_blob(val: Option<&Value>, meta_url: &str) -> Option<String> {
+    val.and_then(|v| v.as_str().or_else(|| v.get("file_id").and_then(Value::as_str)))
+        .map(|id| format!("{}/files/{}", meta_url, id))
+}
+
+pub fn parse_json(val: Option<&Value>) -> Option<String> {
+    val.filter(|v| !v.is_null())
+        .and_then(|v| serde_json::to_string(v).ok())
+}
+
+pub fn parse_point(val: Option<&Value>) -> Option<Coord> {
+    if let Some(Value::String(s)) = val {
+        // an empty point has NaN coordinates, as in sf and WKB
+        return match parse_wkt(s)? {
+            Wkt::Point(point) => Some(point.unwrap_or_else(|| Coord::xy(f64::NAN, f64::NAN))),
+            _ => None,
+        };
+    }
+    val.and_then(|v| v.get("coordinates").and_then(Coord::from_position))
+}
+
+pub fn parse_line(val: Option<&Value>) -> Option<Vec<Coord>> {
+    if let Some(Value::String(s)) = val {
+        return match parse_wkt(s)? {
+            Wkt::LineString(line) => Some(line),
+            _ => None,
+        };
+    }
     val.and_then(|v| {
         v.get("coordinates").and_then(|coords| {
-            coords.as_array().map(|arr| {
-                arr.iter()
-                    .filter_map(|pt| {
-                        if let Some(array) = pt.as_array() {
-                            if array.len() == 2 {
-                                Some((array[0].as_f64()?, array[1].as_f64()?))
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        }
-                    })
-                    .collect::<Vec<(f64, f64)>>()
-            })
+            coords
+                .as_array()
+                .map(|arr| arr.iter().filter_map(Coord::from_position).collect())
         })
     })
 }
 
-pub fn parse_polygon(val: Option<&Value>) -> Option<Vec<Vec<(f64, f64)>>> {
+pub fn parse_polygon(val: Option<&Value>) -> Option<Vec<Vec<Coord>>> {
+    if let Some(Value::String(s)) = val {
+        return match parse_wkt(s)? {
+            Wkt::Polygon(rings) => Some(rings),
+            _ => None,
+        };
+    }
     val.and_then(|v| {
         v.get("coordinates").and_then(|rings| {
-            rings.as_array().map(|ring_arr| {
+            rings.as_array().and_then(|ring_arr| {
                 ring_arr
                     .iter()
                     .map(|ring| {
                         ring.as_array()
-                            .unwrap()
-                            .iter()
-                            .map(|point| {
-                                let lon = point[0].as_f64().unwrap_or(f64::NAN);
-                                let lat = point[1].as_f64().unwrap_or(f64::NAN);
-                                (lon, lat)
-                            })
-                            .collect::<Vec<(f64, f64)>>()
+                            .map(|points| points.iter().map(Coord::from_position_or_nan).collect())
                     })
-                    .collect::<Vec<Vec<(f64, f64)>>>()
+                    .collect::<Option<Vec<Vec<Coord>>>>()
             })
         })
     })
 }
 
-pub fn parse_multipoint(val: Option<&Value>) -> Option<Vec<(f64, f64)>> {
+pub fn parse_multipoint(val: Option<&Value>) -> Option<Vec<Coord>> {
+    if let Some(Value::String(s)) = val {
+        return match parse_wkt(s)? {
+            Wkt::MultiPoint(points) => Some(points),
+            Wkt::Point(point) => Some(point.into_iter().collect()),
+            _ => None,
+        };
+    }
     val.and_then(|v| {
         v.get("coordinates").and_then(|coords| {
-            coords.as_array().map(|arr| {
-                arr.iter()
-                    .filter_map(|pt| {
-                        if let Some(array) = pt.as_array() {
-                            if array.len() == 2 {
-                                Some((array[0].as_f64()?, array[1].as_f64()?))
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        }
-                    })
-                    .collect::<Vec<(f64, f64)>>()
-            })
+            coords
+                .as_array()
+                .map(|arr| arr.iter().filter_map(Coord::from_position).collect())
         })
     })
 }
 
-pub fn parse_multiline(val: Option<&Value>) -> Option<Vec<Vec<(f64, f64)>>> {
+pub fn parse_multiline(val: Option<&Value>) -> Option<Vec<Vec<Coord>>> {
+    if let Some(Value::String(s)) = val {
+        return match parse_wkt(s)? {
+            Wkt::MultiLineString(lines) => Some(lines),
+            Wkt::LineString(line) => Some(single(line)),
+            _ => None,
+        };
+    }
     val.and_then(|v| {
         v.get("coordinates").and_then(|lines| {
-            lines.as_array().map(|lines_arr| {
+            lines.as_array().and_then(|lines_arr| {
                 lines_arr
                     .iter()
                     .map(|line| {
                         line.as_array()
-                            .unwrap()
-                            .iter()
-                            .map(|point| {
-                                let lon = point[0].as_f64().unwrap_or(f64::NAN);
-                                let lat = point[1].as_f64().unwrap_or(f64::NAN);
-                                (lon, lat)
-                            })
-                            .collect::<Vec<(f64, f64)>>()
+                            .map(|points| points.iter().map(Coord::from_position_or_nan).collect())
                     })
-                    .collect::<Vec<Vec<(f64, f64)>>>()
+                    .collect::<Option<Vec<Vec<Coord>>>>()
             })
         })
     })
 }
 
-pub fn parse_multipolygon(val: Option<&Value>) -> Option<Vec<Vec<Vec<(f64, f64)>>>> {
+pub fn parse_multipolygon(val: Option<&Value>) -> Option<Vec<Vec<Vec<Coord>>>> {
+    if let Some(Value::String(s)) = val {
+        return match parse_wkt(s)? {
+            Wkt::MultiPolygon(polygons) => Some(polygons),
+            Wkt::Polygon(rings) => Some(single(rings)),
+            _ => None,
+        };
+    }
     val.and_then(|v| {
         v.get("coordinates").and_then(|polygons| {
-            polygons.as_array().map(|poly_arr| {
+            polygons.as_array().and_then(|poly_arr| {
                 poly_arr
                     .iter()
                     .map(|poly| {
-                        poly.as_array()
-                            .unwrap()
-                            .iter()
-                            .map(|ring| {
-                                ring.as_array()
-                                    .unwrap()
-                                    .iter()
-                                    .map(|point| {
-                                        let lon = point[0].as_f64().unwrap_or(f64::NAN);
-                                        let lat = point[1].as_f64().unwrap_or(f64::NAN);
-                                        (lon, lat)
+                        poly.as_array().and_then(|rings| {
+                            rings
+                                .iter()
+                                .map(|ring| {
+                                    ring.as_array().map(|points| {
+                                        points.iter().map(Coord::from_position_or_nan).collect()
                                     })
-                                    .collect::<Vec<(f64, f64)>>()
-                            })
-                            .collect::<Vec<Vec<(f64, f64)>>>()
+                                })
+                                .collect::<Option<Vec<Vec<Coord>>>>()
+                        })
                     })
-                    .collect::<Vec<Vec<Vec<(f64, f64)>>>>()
+                    .collect::<Option<Vec<Vec<Vec<Coord>>>>>()
             })
         })
     })
 }
 
-pub fn parse_location(
-    val: Option<&Value>,
-) -> (
-    Option<(f64, f64)>,
-    Option<String>,
-    Option<String>,
-    Option<String>,
-    Option<String>,
-) {
+/// A geometry read from well-known text, which Socrata sends instead of
+/// GeoJSON in CSV exports and for some SoQL functions such as `convex_hull`.
+pub enum Wkt {
+    Point(Option<Coord>),
+    LineString(Vec<Coord>),
+    Polygon(Vec<Vec<Coord>>),
+    MultiPoint(Vec<Coord>),
+    MultiLineString(Vec<Vec<Coord>>),
+    MultiPolygon(Vec<Vec<Vec<Coord>>>),
+}
+
+impl Wkt {
+    /// The Socrata datatype of a column holding the geometry.
+    pub fn col_type(&self) -> &'static str {
+        match self {
+            Wkt::Point(_) => "point",
+            Wkt::LineString(_) => "line",
+            Wkt::Polygon(_) => "polygon",
+            Wkt::MultiPoint(_) => "multipoint",
+            Wkt::MultiLineString(_) => "multiline",
+            Wkt::MultiPolygon(_) => "multipolygon",
+        }
+    }
+}
+
+/// Promote a single part to a multi-part geometry, keeping an empty part
+/// empty.
+fn single<T>(part: Vec<T>) -> Vec<Vec<T>> {
+    if part.is_empty() {
+        Vec::new()
+    } else {
+        vec![part]
+    }
+}
+
+/// Read a WKT geometry, with an optional EWKT `SRID=...;` prefix and `Z`,
+/// `M` or `ZM` tag. Without a tag, a third and fourth ordinate are read as z
+/// and m, as in GeoJSON. Geometry collections are not supported.
+pub fn parse_wkt(s: &str) -> Option<Wkt> {
+    let s = s.trim_start();
+    let s = match s.get(..5) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("SRID=") => s.split_once(';')?.1,
+        _ => s,
+    };
+
+    let mut reader = WktReader { s, pos: 0, dim: None };
+    let tag = reader.word()?.to_ascii_uppercase();
+    let dim = match reader.peek_word().map(str::to_ascii_uppercase).as_deref() {
+        Some("Z") => Some(Dim { z: true, m: false }),
+        Some("M") => Some(Dim { z: false, m: true }),
+        Some("ZM") => Some(Dim { z: true, m: true }),
+        _ => None,
+    };
+    if dim.is_some() {
+        reader.word();
+    }
+    reader.dim = dim;
+
+    let wkt = match tag.as_str() {
+        "POINT" => Wkt::Point(reader.empty_or(|r| r.point().map(Some))?.flatten()),
+        "LINESTRING" => Wkt::LineString(reader.coords()?),
+        "POLYGON" => Wkt::Polygon(reader.rings()?),
+        "MULTIPOINT" => Wkt::MultiPoint(reader.list(|r| {
+            // points may or may not be parenthesized
+            if r.peek() == Some('(') {
+                r.point()
+            } else {
+                r.coord()
+            }
+        })?),
+        "MULTILINESTRING" => Wkt::MultiLineString(reader.rings()?),
+        "MULTIPOLYGON" => Wkt::MultiPolygon(reader.list(WktReader::rings)?),
+        _ => return None,
+    };
+
+    reader.skip_ws();
+    (reader.pos == reader.s.len()).then_some(wkt)
+}
+
+/// A parsed Socrata location: a point and the parts of its human-readable
+/// address.
+pub struct Location {
+    pub coord: Option<Coord>,
+    pub address: Option<String>,
+    pub city: Option<String>,
+    pub state: Option<String>,
+    pub zip: Option<String>,
+}
+
+pub fn parse_location(val: Option<&Value>) -> Location {
+    if let Some(s) = val.and_then(Value::as_str) {
+        return parse_location_text(s);
+    }
     let location = val.and_then(|v| v.as_object());
 
     let lat = location
@@ -228,8 +409,8 @@ pub fn parse_location(
             })
         });
 
-    let latlon = match (lat, lon) {
-        (Some(lat), Some(lon)) => Some((lat, lon)),
+    let coord = match (lat, lon) {
+        (Some(lat), Some(lon)) => Some(Coord::from_lat_lon(lat, lon)),
         _ => None,
     };
 
@@ -267,5 +448,155 @@ pub fn parse_location(
         .and_then(|v| v.as_str())
         .map(str::to_string);
 
-    (latlon, addr, city, state, zip)
+    Location {
+        coord,
+        address: addr,
+        city,
+        state,
+        zip,
+    }
+}
+
+/// Read a location sent as text by CSV pages: either a WKT point, or an
+/// address followed by a `(latitude, longitude)` pair.
+fn parse_location_text(s: &str) -> Location {
+    let mut location = Location {
+        coord: None,
+        address: None,
+        city: None,
+        state: None,
+        zip: None,
+    };
+    if let Some(Wkt::Point(coord)) = parse_wkt(s) {
+        location.coord = coord;
+        return location;
+    }
+
+    let mut address = s.trim();
+    if let Some(start) = address.strip_suffix(')').and_then(|rest| rest.rfind('(')) {
+        let lat_lon = address[start + 1..address.len() - 1].split_once(',');
+        let lat_lon = lat_lon.and_then(|(lat, lon)| {
+            Some((lat.trim().parse::<f64>().ok()?, lon.trim().parse::<f64>().ok()?))
+        });
+        if let Some((lat, lon)) = lat_lon {
+            location.coord = Some(Coord::from_lat_lon(lat, lon));
+            address = address[..start].trim();
+        }
+    }
+    location.address = (!address.is_empty()).then(|| address.to_string());
+    location
+}
+
+/// A cursor over the text of a WKT geometry.
+struct WktReader<'a> {
+    s: &'a str,
+    pos: usize,
+    // the dimension given by a `Z`, `M` or `ZM` tag
+    dim: Option<Dim>,
+}
+
+impl<'a> WktReader<'a> {
+    fn skip_ws(&mut self) {
+        let rest = &self.s[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_ws();
+        self.s[self.pos..].chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> Option<()> {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn peek_word(&mut self) -> Option<&'a str> {
+        self.skip_ws();
+        let rest = &self.s[self.pos..];
+        let len = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        (len > 0).then(|| &rest[..len])
+    }
+
+    fn word(&mut self) -> Option<&'a str> {
+        let word = self.peek_word()?;
+        self.pos += word.len();
+        Some(word)
+    }
+
+    fn number(&mut self) -> Option<f64> {
+        self.skip_ws();
+        let rest = &self.s[self.pos..];
+        let len = rest
+            .find(|c: char| c.is_whitespace() || c == ',' || c == ')')
+            .unwrap_or(rest.len());
+        let number = rest[..len].parse::<f64>().ok()?;
+        self.pos += len;
+        Some(number)
+    }
+
+    /// `EMPTY`, read as `None`, or the item read by `read`.
+    fn empty_or<T>(&mut self, read: impl FnOnce(&mut Self) -> Option<T>) -> Option<Option<T>> {
+        match self.peek_word() {
+            Some(word) if word.eq_ignore_ascii_case("EMPTY") => {
+                self.word();
+                Some(None)
+            }
+            _ => read(self).map(Some),
+        }
+    }
+
+    /// A parenthesized, comma-separated list of items, or `EMPTY`.
+    fn list<T>(&mut self, mut read: impl FnMut(&mut Self) -> Option<T>) -> Option<Vec<T>> {
+        let items = self.empty_or(|r| {
+            r.eat('(')?;
+            let mut items = vec![read(r)?];
+            while r.eat(',').is_some() {
+                items.push(read(r)?);
+            }
+            r.eat(')')?;
+            Some(items)
+        })?;
+        Some(items.unwrap_or_default())
+    }
+
+    fn coord(&mut self) -> Option<Coord> {
+        let mut ordinates = [f64::NAN; 4];
+        let mut n = 0;
+        while n < 4 && !matches!(self.peek(), Some(',' | ')') | None) {
+            ordinates[n] = self.number()?;
+            n += 1;
+        }
+        let [x, y, third, fourth] = ordinates;
+        let (z, m) = match self.dim {
+            None if n >= 2 => (third, fourth),
+            Some(dim) if n == dim.n_ordinates() => match (dim.z, dim.m) {
+                (false, true) => (f64::NAN, third),
+                _ => (third, fourth),
+            },
+            _ => return None,
+        };
+        Some(Coord { x, y, z, m })
+    }
+
+    fn point(&mut self) -> Option<Coord> {
+        self.eat('(')?;
+        let coord = self.coord()?;
+        self.eat(')')?;
+        Some(coord)
+    }
+
+    fn coords(&mut self) -> Option<Vec<Coord>> {
+        self.list(WktReader::coord)
+    }
+
+    fn rings(&mut self) -> Option<Vec<Vec<Coord>>> {
+        self.list(WktReader::coords)
+    }
 }
@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::error::ParseError;
+use crate::reader::{Chunk, Reader};
+
+type PageResult = (usize, Result<Chunk, ParseError>);
+
+/// Parses pages on background threads as they arrive.
+///
+/// Each page is copied and handed to a pool of workers, so R can go on to
+/// download the next page while earlier ones are parsed. At most two pages per
+/// worker wait to be parsed; beyond that, pushing a page blocks until a worker
+/// is free, so memory stays bounded when downloads outpace parsing. Parsed
+/// pages are appended to the [`Reader`] in page order, whichever worker
+/// finishes first.
+pub struct PagePool {
+    reader: Reader,
+    sender: Option<SyncSender<(usize, Vec<u8>)>>,
+    results: Receiver<PageResult>,
+    workers: Vec<JoinHandle<()>>,
+    // parsed pages waiting for an earlier page to finish
+    pending: BTreeMap<usize, Result<Chunk, ParseError>>,
+    n_sent: usize,
+    n_done: usize,
+    error: Option<ParseError>,
+}
+
+impl PagePool {
+    pub fn new(reader: Reader, n_threads: usize) -> PagePool {
+        let parsers = (0..n_threads.max(1))
+            .map(|_| {
+                let mut worker = reader.fork(0);
+                move |page, bytes: &[u8]| worker.parse_page(page, bytes)
+            })
+            .collect();
+        PagePool::with_parsers(reader, parsers)
+    }
+
+    /// Start a worker for each of `parsers`. A worker whose parser panics
+    /// reports [`ParseError::Thread`] for that page and stops, as its state
+    /// can no longer be trusted.
+    fn with_parsers<F>(reader: Reader, parsers: Vec<F>) -> PagePool
+    where
+        F: FnMut(usize, &[u8]) -> Result<Chunk, ParseError> + Send + 'static,
+    {
+        let (sender, pages) = mpsc::sync_channel::<(usize, Vec<u8>)>(2 * parsers.len());
+        let (results_sender, results) = mpsc::channel();
+        let pages = Arc::new(Mutex::new(pages));
+
+        let workers = parsers
+            .into_iter()
+            .map(|mut parse| {
+                let pages = Arc::clone(&pages);
+                let results = results_sender.clone();
+                thread::spawn(move || loop {
+                    let message = match pages.lock() {
+                        Ok(pages) => pages.recv(),
+                        Err(_) => return,
+                    };
+                    let (page, bytes) = match message {
+                        Ok(message) => message,
+                        Err(_) => return,
+                    };
+                    match panic::catch_unwind(AssertUnwindSafe(|| parse(page, &bytes))) {
+                        Ok(result) => {
+                            if results.send((page, result)).is_err() {
+                                return;
+                            }
+                        }
+                        Err(_) => {
+                            results.send((page, Err(ParseError::Thread))).ok();
+                            return;
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        PagePool {
+            reader,
+            sender: Some(sender),
+            results,
+            workers,
+            pending: BTreeMap::new(),
+            n_sent: 0,
+            n_done: 0,
+            error: None,
+        }
+    }
+
+    /// Queue a page for parsing, waiting for a worker if the queue is full.
+    ///
+    /// Returns the first error from any page parsed so far, without waiting
+    /// for pages that are still being parsed.
+    pub fn push_page(&mut self, bytes: &[u8]) -> Result<(), ParseError> {
+        self.n_sent += 1;
+        if let Some(sender) = &self.sender {
+            sender
+                .send((self.n_sent, bytes.to_vec()))
+                .map_err(|_| ParseError::Thread)?;
+        }
+
+        while let Ok(result) = self.results.try_recv() {
+            self.absorb(result);
+        }
+        self.check()
+    }
+
+    /// Number of pages queued so far.
+    pub fn n_pages(&self) -> usize {
+        self.n_sent
+    }
+
+    /// Number of rows in every page queued so far, waiting for them to be
+    /// parsed.
+    pub fn n_rows(&mut self) -> Result<usize, ParseError> {
+        self.wait()?;
+        self.check()?;
+        Ok(self.reader.n_rows())
+    }
+
+    pub fn reader(&self) -> &Reader {
+        &self.reader
+    }
+
+    /// Wait for every queued page and return the reader holding all rows.
+    pub fn finish(mut self) -> Result<Reader, ParseError> {
+        self.wait()?;
+        self.sender = None;
+        for worker in self.workers.drain(..) {
+            worker.join().map_err(|_| ParseError::Thread)?;
+        }
+        self.check()?;
+        Ok(self.reader)
+    }
+
+    fn wait(&mut self) -> Result<(), ParseError> {
+        while self.n_done + self.pending.len() < self.n_sent {
+            let result = self.results.recv().map_err(|_| ParseError::Thread)?;
+            self.absorb(result);
+        }
+        Ok(())
+    }
+
+    fn absorb(&mut self, (page, result): PageResult) {
+        self.pending.insert(page, result);
+        while let Some(result) = self.pending.remove(&(self.n_done + 1)) {
+            self.n_done += 1;
+            match result {
+                Ok(chunk) if self.error.is_none() => self.reader.append_chunk(chunk),
+                Ok(_) => {}
+                Err(err) => {
+                    self.error.get_or_insert(err);
+                }
+            }
+        }
+    }
+
+    fn check(&self) -> Result<(), ParseError> {
+        match &self.error {
+            Some(err) => Err(err.clone()),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::Format;
+    use crate::time::Floating;
+
+    #[test]
+    fn a_panicking_worker_fails_the_pool_instead_of_hanging() {
+        let reader = Reader::new(
+            vec!["a".to_string()],
+            vec!["text".to_string()],
+            Floating::Naive,
+            "",
+            false,
+            Format::Json,
+            false,
+        );
+        let parsers = (0..2)
+            .map(|_| {
+                let mut worker = reader.fork(0);
+                move |page, bytes: &[u8]| {
+                    if page == 2 {
+                        panic!("worker failure");
+                    }
+                    worker.parse_page(page, bytes)
+                }
+            })
+            .collect();
+        let mut pool = PagePool::with_parsers(reader, parsers);
+
+        for _ in 0..4 {
+            if pool.push_page(br#"[{"a": "x"}]"#).is_err() {
+                break;
+            }
+        }
+        assert!(matches!(pool.n_rows(), Err(ParseError::Thread)));
+        assert!(matches!(pool.finish(), Err(ParseError::Thread)));
+    }
+}
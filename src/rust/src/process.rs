@@ -1,59 +1,339 @@
+use chrono::{DateTime, NaiveTime, SecondsFormat, Utc};
 use extendr_api::prelude::*;
 
+use crate::error::ParseError;
+use crate::geom::{Coord, Dim};
+use crate::time::Floating;
+use crate::{wkb, wkt};
+
 pub enum Column {
     Boolean(Vec<Option<bool>>),
     Number(Vec<Option<f64>>),
-    FixedTimestamp(Vec<Option<f64>>),
-    FloatingTimestamp(Vec<Option<f64>>),
+    FixedTimestamp(Vec<Option<DateTime<Utc>>>),
+    FloatingTimestamp(Vec<Option<DateTime<Utc>>>),
     Text(Vec<Option<String>>),
+    RowId(Vec<Option<String>>),
     Url((Vec<Option<String>>, Vec<Option<String>>)),
     Photo(Vec<Option<String>>),
     Document(Vec<Option<String>>),
-    Point(Vec<Option<(f64, f64)>>),
-    Line(Vec<Option<Vec<(f64, f64)>>>),
-    Polygon(Vec<Option<Vec<Vec<(f64, f64)>>>>),
-    MultiPoint(Vec<Option<Vec<(f64, f64)>>>),
-    MultiLine(Vec<Option<Vec<Vec<(f64, f64)>>>>),
-    MultiPolygon(Vec<Option<Vec<Vec<Vec<(f64, f64)>>>>>),
-    Location(
-        (
-            Vec<Option<(f64, f64)>>, // lat/lon pairs
-            Vec<Option<String>>,     // addresses
-            Vec<Option<String>>,     // cities
-            Vec<Option<String>>,     // states
-            Vec<Option<String>>,     // zips
-        ),
-    ),
+    Checkbox(Vec<Option<bool>>),
+    Money(Vec<Option<f64>>),
+    Percent(Vec<Option<f64>>),
+    Date(Vec<Option<DateTime<Utc>>>),
+    CalendarDate(Vec<Option<DateTime<Utc>>>),
+    Html(Vec<Option<String>>),
+    Email(Vec<Option<String>>),
+    Phone((Vec<Option<String>>, Vec<Option<String>>)),
+    Blob(Vec<Option<String>>),
+    Json(Vec<Option<String>>),
+    Point(Vec<Option<Coord>>),
+    Line(Vec<Option<Vec<Coord>>>),
+    Polygon(Vec<Option<Vec<Vec<Coord>>>>),
+    MultiPoint(Vec<Option<Vec<Coord>>>),
+    MultiLine(Vec<Option<Vec<Vec<Coord>>>>),
+    MultiPolygon(Vec<Option<Vec<Vec<Vec<Coord>>>>>),
+    Location(LocationColumn),
 }
 
-/// Convert a vector of named columns into an R data.frame.
-pub fn as_rlist(col_names: Vec<String>, columns: Vec<Column>) -> List {
-    let robj_columns: Vec<Robj> = columns
-        .into_iter()
-        .map(|column| match column {
-            Column::Boolean(values) => as_logical(values),
-            Column::Number(values) => as_numeric(values),
-            Column::FixedTimestamp(values) => as_posixct_utc(values),
-            Column::FloatingTimestamp(values) => as_posixct_naive(values),
-            Column::Text(values) => as_character(values),
-            Column::Point(values) => as_point_sfc(values),
-            Column::Url((urls, descs)) => as_url_list(urls, descs),
-            Column::Photo(values) => as_character(values), //
-            Column::Document(values) => as_character(values),
-            Column::Line(values) => as_line_sfc(values),
-            Column::Polygon(values) => as_polygon_sfc(values),
-            Column::MultiPoint(vec) => as_multipoint_sfc(vec),
-            Column::MultiLine(vec) => as_multiline_sfc(vec),
-            Column::MultiPolygon(values) => as_multipolygon_sfc(values),
-            Column::Location((coords, addresses, cities, states, zips)) => {
-                as_location_list(coords, addresses, cities, states, zips)
+/// Values of a location column: points and the parts of their addresses.
+#[derive(Default)]
+pub struct LocationColumn {
+    pub coords: Vec<Option<Coord>>,
+    pub addresses: Vec<Option<String>>,
+    pub cities: Vec<Option<String>>,
+    pub states: Vec<Option<String>>,
+    pub zips: Vec<Option<String>>,
+}
+
+impl Column {
+    /// Coordinate dimensions of a geometry column, the highest of any of its
+    /// coordinates. Other columns are XY.
+    pub fn dim(&self) -> Dim {
+        match self {
+            Column::Point(values) => Dim::of(values.iter().flatten()),
+            Column::Line(values) | Column::MultiPoint(values) => {
+                Dim::of(values.iter().flatten().flatten())
+            }
+            Column::Polygon(values) | Column::MultiLine(values) => {
+                Dim::of(values.iter().flatten().flatten().flatten())
+            }
+            Column::MultiPolygon(values) => {
+                Dim::of(values.iter().flatten().flatten().flatten().flatten())
             }
+            _ => Dim::XY,
+        }
+    }
+}
+
+/// R class used for timestamp columns.
+#[derive(Clone, Copy)]
+pub enum TimestampClass {
+    /// POSIXct seconds, precise to about a microsecond.
+    Posixct,
+    /// `integer64` nanoseconds, which the nanotime package can wrap.
+    Nanotime,
+}
+
+impl TimestampClass {
+    pub fn from_name(name: &str) -> Option<TimestampClass> {
+        match name {
+            "POSIXct" => Some(TimestampClass::Posixct),
+            "nanotime" => Some(TimestampClass::Nanotime),
+            _ => None,
+        }
+    }
+}
+
+/// R class used for geometry columns.
+#[derive(Clone, Copy)]
+pub enum GeometryClass {
+    /// sf `sfc` lists, which need the sf package.
+    Sfc,
+    /// wk `wk_wkb` lists of well-known binary raw vectors.
+    Wkb,
+    /// wk `wk_wkt` well-known text strings.
+    Wkt,
+}
+
+impl GeometryClass {
+    pub fn from_name(name: &str) -> Option<GeometryClass> {
+        match name {
+            "sf" => Some(GeometryClass::Sfc),
+            "wkb" => Some(GeometryClass::Wkb),
+            "wkt" => Some(GeometryClass::Wkt),
+            _ => None,
+        }
+    }
+}
+
+/// Floating timestamp columns returned as R `Date`s.
+pub enum DateColumns {
+    /// Columns with at least one value, all of them at midnight.
+    Auto,
+    /// The named columns, dropping any time of day.
+    Named(Vec<String>),
+}
+
+impl DateColumns {
+    fn is_date(&self, name: &str, values: &[Option<DateTime<Utc>>], floating: Floating) -> bool {
+        match self {
+            DateColumns::Auto => all_midnight(values, floating),
+            DateColumns::Named(names) => names.iter().any(|date_name| date_name == name),
+        }
+    }
+
+    /// The columns to return as dates for data split into `parts`, each with
+    /// the columns of `col_names`, judging all parts together so that parts
+    /// converted apart agree.
+    pub fn across(
+        &self,
+        col_names: &[String],
+        parts: &[&[Column]],
+        floating: Floating,
+    ) -> DateColumns {
+        let names = match self {
+            DateColumns::Auto => col_names
+                .iter()
+                .enumerate()
+                .filter(|&(c, _)| {
+                    let values = parts.iter().flat_map(|columns| match &columns[c] {
+                        Column::FloatingTimestamp(values) | Column::CalendarDate(values) => {
+                            values.as_slice()
+                        }
+                        _ => &[],
+                    });
+                    all_midnight(values, floating)
+                })
+                .map(|(_, name)| name.clone())
+                .collect(),
+            DateColumns::Named(names) => names.clone(),
+        };
+        DateColumns::Named(names)
+    }
+}
+
+/// Whether there is at least one value and every value is at midnight.
+fn all_midnight<'a, I>(values: I, floating: Floating) -> bool
+where
+    I: IntoIterator<Item = &'a Option<DateTime<Utc>>>,
+{
+    let mut any = false;
+    values.into_iter().flatten().all(|instant| {
+        any = true;
+        floating.wall_clock(*instant).time() == NaiveTime::MIN
+    }) && any
+}
+
+/// How parsed columns are converted to R vectors.
+pub struct OutputOptions {
+    pub timestamp_class: TimestampClass,
+    pub date_columns: DateColumns,
+    pub geometry_class: GeometryClass,
+}
+
+impl OutputOptions {
+    /// Timestamps of a column that are returned as nanotime but fall outside
+    /// its range, as their rows and values. Floating timestamps are shown as
+    /// wall-clock times.
+    pub fn out_of_range(
+        &self,
+        name: &str,
+        column: &Column,
+        floating: Floating,
+    ) -> Vec<(usize, String)> {
+        if !matches!(self.timestamp_class, TimestampClass::Nanotime) {
+            return Vec::new();
+        }
+        let (values, is_floating) = match column {
+            Column::FixedTimestamp(values) | Column::Date(values) => (values, false),
+            Column::FloatingTimestamp(values) | Column::CalendarDate(values)
+                if matches!(floating, Floating::Zoned { .. })
+                    && !self.date_columns.is_date(name, values, floating) =>
+            {
+                (values, true)
+            }
+            _ => return Vec::new(),
+        };
+        values
+            .iter()
+            .enumerate()
+            .filter_map(|(row, opt)| {
+                let instant = opt.filter(|instant| instant.timestamp_nanos_opt().is_none())?;
+                let value = if is_floating {
+                    floating
+                        .wall_clock(instant)
+                        .format("%Y-%m-%dT%H:%M:%S%.f")
+                        .to_string()
+                } else {
+                    instant.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+                };
+                Some((row, value))
+            })
+            .collect()
+    }
+}
+
+/// Convert a vector of named columns into an R data.frame.
+///
+/// Floating timestamps read with [`Floating::Naive`] are returned as ISO 8601
+/// strings of class `socratadata_naive_time`, unless `date_columns` picks them
+/// as dates. Fails only when sf geometries are asked for without sf.
+pub fn as_rlist(
+    col_names: Vec<String>,
+    columns: Vec<Column>,
+    floating: Floating,
+    options: &OutputOptions,
+) -> std::result::Result<List, ParseError> {
+    let OutputOptions {
+        timestamp_class,
+        date_columns,
+        geometry_class,
+    } = options;
+    let timestamp_class = *timestamp_class;
+    let as_timestamp = |values, is_floating| match (timestamp_class, floating) {
+        (_, Floating::Naive) if is_floating => as_naive_time(values),
+        (TimestampClass::Nanotime, _) => as_integer64(values),
+        (TimestampClass::Posixct, Floating::Zoned { tz, session, .. }) if is_floating => {
+            as_posixct(values, if session { "" } else { tz.name() })
+        }
+        (TimestampClass::Posixct, _) => as_posixct(values, "UTC"),
+    };
+    let as_floating = |name: &str, values: Vec<Option<DateTime<Utc>>>| {
+        if date_columns.is_date(name, &values, floating) {
+            as_date(values, floating)
+        } else {
+            as_timestamp(values, true)
+        }
+    };
+
+    let robj_columns = col_names
+        .iter()
+        .zip(columns)
+        .map(|(name, column)| {
+            let dim = column.dim();
+            let robj = match column {
+                Column::Boolean(values) => as_logical(values),
+                Column::Number(values) => as_numeric(values),
+                Column::FixedTimestamp(values) => as_timestamp(values, false),
+                Column::FloatingTimestamp(values) => as_floating(name, values),
+                Column::Text(values) => as_character(values),
+                Column::RowId(values) => as_row_id(values),
+                Column::Point(values) => match geometry_class {
+                    GeometryClass::Sfc => as_point_sfc(values, dim)?,
+                    wk => as_wk(
+                        &values,
+                        *wk,
+                        |out, pt| wkb::write_point(out, *pt, dim),
+                        |out, pt| wkt::write_point(out, *pt, dim),
+                    ),
+                },
+                Column::Url((urls, descs)) => as_url_list(urls, descs),
+                Column::Photo(values) => as_character(values), //
+                Column::Document(values) => as_character(values),
+                Column::Checkbox(values) => as_logical(values),
+                Column::Money(values) => as_numeric(values),
+                Column::Percent(values) => as_numeric(values),
+                Column::Date(values) => as_timestamp(values, false),
+                Column::CalendarDate(values) => as_floating(name, values),
+                Column::Html(values) => as_character(values),
+                Column::Email(values) => as_character(values),
+                Column::Phone((numbers, types)) => as_phone_list(numbers, types),
+                Column::Blob(values) => as_character(values),
+                Column::Json(values) => as_json(values),
+                Column::Line(values) => match geometry_class {
+                    GeometryClass::Sfc => as_line_sfc(values, dim)?,
+                    wk => as_wk(
+                        &values,
+                        *wk,
+                        |out, geom| wkb::write_linestring(out, geom, dim),
+                        |out, geom| wkt::write_linestring(out, geom, dim),
+                    ),
+                },
+                Column::Polygon(values) => match geometry_class {
+                    GeometryClass::Sfc => as_polygon_sfc(values, dim)?,
+                    wk => as_wk(
+                        &values,
+                        *wk,
+                        |out, geom| wkb::write_polygon(out, geom, dim),
+                        |out, geom| wkt::write_polygon(out, geom, dim),
+                    ),
+                },
+                Column::MultiPoint(values) => match geometry_class {
+                    GeometryClass::Sfc => as_multipoint_sfc(values, dim)?,
+                    wk => as_wk(
+                        &values,
+                        *wk,
+                        |out, geom| wkb::write_multipoint(out, geom, dim),
+                        |out, geom| wkt::write_multipoint(out, geom, dim),
+                    ),
+                },
+                Column::MultiLine(values) => match geometry_class {
+                    GeometryClass::Sfc => as_multiline_sfc(values, dim)?,
+                    wk => as_wk(
+                        &values,
+                        *wk,
+                        |out, geom| wkb::write_multilinestring(out, geom, dim),
+                        |out, geom| wkt::write_multilinestring(out, geom, dim),
+                    ),
+                },
+                Column::MultiPolygon(values) => match geometry_class {
+                    GeometryClass::Sfc => as_multipolygon_sfc(values, dim)?,
+                    wk => as_wk(
+                        &values,
+                        *wk,
+                        |out, geom| wkb::write_multipolygon(out, geom, dim),
+                        |out, geom| wkt::write_multipolygon(out, geom, dim),
+                    ),
+                },
+                Column::Location(location) => as_location_list(location, *geometry_class)?,
+            };
+            Ok(robj)
         })
-        .collect();
+        .collect::<std::result::Result<Vec<Robj>, ParseError>>()?;
 
     let rlist = List::from_names_and_values(col_names, robj_columns);
 
-    rlist.unwrap()
+    Ok(rlist.unwrap())
 }
 
 fn as_logical(values: Vec<Option<bool>>) -> Robj {
@@ -72,26 +352,74 @@ fn as_numeric(values: Vec<Option<f64>>) -> Robj {
     Robj::from(vec)
 }
 
-fn as_posixct_utc(values: Vec<Option<f64>>) -> Robj {
-    let vec: Vec<Rfloat> = values
+/// Convert instants into POSIXct seconds since the epoch, adding the whole
+/// seconds and the fraction separately to keep as much precision as a double
+/// allows.
+fn as_seconds(values: Vec<Option<DateTime<Utc>>>) -> Vec<Rfloat> {
+    values
         .into_iter()
-        .map(|opt| opt.map_or(Rfloat::na(), Rfloat::from))
+        .map(|opt| {
+            opt.map_or(Rfloat::na(), |instant| {
+                let secs = instant.timestamp() as f64;
+                Rfloat::from(secs + instant.timestamp_subsec_nanos() as f64 / 1e9)
+            })
+        })
+        .collect()
+}
+
+fn as_posixct(values: Vec<Option<DateTime<Utc>>>, tzone: &str) -> Robj {
+    let mut robj = r!(as_seconds(values));
+    robj.set_class(&["POSIXct", "POSIXt"]).unwrap();
+    robj.set_attrib("tzone", tzone).unwrap();
+    robj
+}
+
+fn as_naive_time(values: Vec<Option<DateTime<Utc>>>) -> Robj {
+    let vec: Vec<Option<String>> = values
+        .into_iter()
+        .map(|opt| {
+            opt.map(|instant| {
+                instant
+                    .naive_utc()
+                    .format("%Y-%m-%dT%H:%M:%S%.f")
+                    .to_string()
+            })
+        })
         .collect();
+    let mut robj = as_character(vec);
+    robj.set_class(&["socratadata_naive_time"]).unwrap();
+    robj
+}
 
+/// Days since the epoch of the wall-clock date of each timestamp.
+fn as_date(values: Vec<Option<DateTime<Utc>>>, floating: Floating) -> Robj {
+    let vec: Vec<Rint> = values
+        .into_iter()
+        .map(|opt| {
+            opt.map_or(Rint::na(), |instant| {
+                let date = floating.wall_clock(instant).date();
+                Rint::from((date - DateTime::UNIX_EPOCH.date_naive()).num_days() as i32)
+            })
+        })
+        .collect();
     let mut robj = r!(vec);
-    robj.set_class(&["POSIXct", "POSIXt"]).unwrap();
-    robj.set_attrib("tzone", "UTC").unwrap();
+    robj.set_class(&["Date"]).unwrap();
     robj
 }
 
-fn as_posixct_naive(values: Vec<Option<f64>>) -> Robj {
-    let vec: Vec<Rfloat> = values
+/// Convert instants into nanoseconds since the epoch as bit64's `integer64`,
+/// which stores each i64 in the bits of a double, with `i64::MIN` as NA.
+/// Instants outside the years 1677 to 2262 do not fit and are missing.
+fn as_integer64(values: Vec<Option<DateTime<Utc>>>) -> Robj {
+    let vec: Vec<f64> = values
         .into_iter()
-        .map(|opt| opt.map_or(Rfloat::na(), Rfloat::from))
+        .map(|opt| {
+            let nanos = opt.and_then(|instant| instant.timestamp_nanos_opt());
+            f64::from_bits(nanos.unwrap_or(i64::MIN) as u64)
+        })
         .collect();
     let mut robj = r!(vec);
-    robj.set_class(&["POSIXct", "POSIXt"]).unwrap();
-    robj.set_attrib("tzone", "").unwrap();
+    robj.set_class(&["integer64"]).unwrap();
     robj
 }
 
@@ -100,6 +428,13 @@ fn as_character(values: Vec<Option<String>>) -> Robj {
     r!(vec)
 }
 
+fn as_row_id(values: Vec<Option<String>>) -> Robj {
+    let mut robj = as_character(values);
+    robj.set_class(&["socratadata_row_id", "character"])
+        .unwrap();
+    robj
+}
+
 fn as_url_list(urls: Vec<Option<String>>, descs: Vec<Option<String>>) -> Robj {
     let url = as_character(urls);
     let description = as_character(descs);
@@ -109,193 +444,201 @@ fn as_url_list(urls: Vec<Option<String>>, descs: Vec<Option<String>>) -> Robj {
     url_list.into_robj()
 }
 
-fn as_point_sfc(values: Vec<Option<(f64, f64)>>) -> Robj {
+fn as_phone_list(numbers: Vec<Option<String>>, types: Vec<Option<String>>) -> Robj {
+    let phone_number = as_character(numbers);
+    let phone_type = as_character(types);
+
+    let phone_list = list!(phone_number = phone_number, phone_type = phone_type);
+
+    phone_list.into_robj()
+}
+
+fn as_json(values: Vec<Option<String>>) -> Robj {
+    let mut robj = as_character(values);
+    robj.set_class(&["json"]).unwrap();
+    robj
+}
+
+/// Coordinates as rows of a matrix, as sf stores linestrings and rings.
+fn as_matrix(coords: &[Coord], dim: Dim) -> Robj {
+    let n = dim.n_ordinates();
+    let flat_coords: Vec<f64> = coords.iter().flat_map(|pos| dim.ordinates(*pos)).collect();
+    let matrix = RMatrix::new_matrix(coords.len(), n, |r, c| flat_coords[r * n + c]);
+    Robj::from(matrix)
+}
+
+fn as_matrix_list(lines: &[Vec<Coord>], dim: Dim) -> Robj {
+    let matrices: Vec<Robj> = lines.iter().map(|line| as_matrix(line, dim)).collect();
+    Robj::from(matrices)
+}
+
+fn as_sfg(mut robj: Robj, geom_type: &str, dim: Dim) -> Robj {
+    robj.set_class(&[dim.name(), geom_type, "sfg"]).unwrap();
+    robj
+}
+
+fn as_point_sfc(values: Vec<Option<Coord>>, dim: Dim) -> std::result::Result<Robj, ParseError> {
     let mut n_empty = 0;
+    let mut bbox = Bbox::new();
     let list: Vec<Robj> = values
         .into_iter()
         .map(|opt| {
-            let coords = match opt {
-                Some((lon, lat)) => Robj::from(vec![lon, lat]),
+            let coords: Vec<f64> = match opt {
+                // `POINT EMPTY`, as sf stores it
+                Some(point) if point.x.is_nan() && point.y.is_nan() => {
+                    n_empty += 1;
+                    dim.ordinates(point).collect()
+                }
+                Some(point) => {
+                    bbox.add(point);
+                    dim.ordinates(point).collect()
+                }
                 None => {
                     n_empty += 1;
-                    Robj::from(vec![f64::NAN, f64::NAN])
+                    vec![f64::NAN; dim.n_ordinates()]
                 }
             };
-            let mut robj = Robj::from(coords);
-            robj.set_class(&["XY", "POINT", "sfg"]).unwrap();
-            robj
+            as_sfg(Robj::from(coords), "POINT", dim)
         })
         .collect();
 
-    as_sfc(Robj::from(list), "point", n_empty)
+    as_sfc(Robj::from(list), "point", dim, n_empty, bbox)
 }
 
-fn as_line_sfc(values: Vec<Option<Vec<(f64, f64)>>>) -> Robj {
+fn as_line_sfc(values: Vec<Option<Vec<Coord>>>, dim: Dim) -> std::result::Result<Robj, ParseError> {
     let mut n_empty = 0;
+    let mut bbox = Bbox::new();
     let list: Vec<Robj> = values
         .into_iter()
-        .map(|opt| match opt {
-            Some(line) => {
-                let flat_coords: Vec<f64> = line.iter().flat_map(|(x, y)| vec![*x, *y]).collect();
-                let matrix = RMatrix::new_matrix(line.len(), 2, |r, c| flat_coords[r * 2 + c]);
-                let mut robj = Robj::from(matrix);
-                robj.set_class(&["XY", "LINESTRING", "sfg"]).unwrap();
-                robj
-            }
-            None => {
+        .map(|opt| {
+            let line = opt.unwrap_or_else(|| {
                 n_empty += 1;
-                let empty_matrix = RMatrix::new_matrix(0, 2, |_, _| 0.0);
-                let mut robj = Robj::from(empty_matrix);
-                robj.set_class(&["XY", "LINESTRING", "sfg"]).unwrap();
-                robj
-            }
+                Vec::new()
+            });
+            bbox.extend(&line);
+            as_sfg(as_matrix(&line, dim), "LINESTRING", dim)
         })
         .collect();
 
-    as_sfc(Robj::from(list), "linestring", n_empty)
+    as_sfc(Robj::from(list), "linestring", dim, n_empty, bbox)
 }
 
-fn as_polygon_sfc(values: Vec<Option<Vec<Vec<(f64, f64)>>>>) -> Robj {
+fn as_polygon_sfc(
+    values: Vec<Option<Vec<Vec<Coord>>>>,
+    dim: Dim,
+) -> std::result::Result<Robj, ParseError> {
     let mut n_empty = 0;
+    let mut bbox = Bbox::new();
     let list: Vec<Robj> = values
         .into_iter()
-        .map(|opt| match opt {
-            Some(polygon) => {
-                let rings: Vec<Robj> = polygon
-                    .into_iter()
-                    .map(|ring| {
-                        let flat_coords: Vec<f64> =
-                            ring.iter().flat_map(|(x, y)| vec![*x, *y]).collect();
-                        let matrix =
-                            RMatrix::new_matrix(ring.len(), 2, |r, c| flat_coords[r * 2 + c]);
-                        Robj::from(matrix)
-                    })
-                    .collect();
-
-                let mut robj = Robj::from(rings);
-                robj.set_class(&["XY", "POLYGON", "sfg"]).unwrap();
-                robj
-            }
-            None => {
+        .map(|opt| {
+            let polygon = opt.unwrap_or_else(|| {
                 n_empty += 1;
-                let mut robj = Robj::from(List::from_values(Vec::<Robj>::new()));
-                robj.set_class(&["XY", "POLYGON", "sfg"]).unwrap();
-                robj
-            }
+                Vec::new()
+            });
+            polygon.iter().for_each(|ring| bbox.extend(ring));
+            as_sfg(as_matrix_list(&polygon, dim), "POLYGON", dim)
         })
         .collect();
 
-    as_sfc(Robj::from(list), "polygon", n_empty)
+    as_sfc(Robj::from(list), "polygon", dim, n_empty, bbox)
 }
 
-fn as_multipoint_sfc(values: Vec<Option<Vec<(f64, f64)>>>) -> Robj {
+fn as_multipoint_sfc(
+    values: Vec<Option<Vec<Coord>>>,
+    dim: Dim,
+) -> std::result::Result<Robj, ParseError> {
     let mut n_empty = 0;
+    let mut bbox = Bbox::new();
     let list: Vec<Robj> = values
         .into_iter()
-        .map(|opt| match opt {
-            Some(multipoint) => {
-                let flat_coords: Vec<f64> =
-                    multipoint.iter().flat_map(|(x, y)| vec![*x, *y]).collect();
-                let matrix =
-                    RMatrix::new_matrix(multipoint.len(), 2, |r, c| flat_coords[r * 2 + c]);
-                let mut robj = Robj::from(matrix);
-                robj.set_class(&["XY", "MULTIPOINT", "sfg"]).unwrap();
-                robj
-            }
-            None => {
+        .map(|opt| {
+            let multipoint = opt.unwrap_or_else(|| {
                 n_empty += 1;
-                let empty_matrix = RMatrix::new_matrix(0, 2, |_, _| 0.0);
-                let mut robj = Robj::from(empty_matrix);
-                robj.set_class(&["XY", "MULTIPOINT", "sfg"]).unwrap();
-                robj
-            }
+                Vec::new()
+            });
+            bbox.extend(&multipoint);
+            as_sfg(as_matrix(&multipoint, dim), "MULTIPOINT", dim)
         })
         .collect();
 
-    as_sfc(Robj::from(list), "multipoint", n_empty)
+    as_sfc(Robj::from(list), "multipoint", dim, n_empty, bbox)
 }
 
-fn as_multiline_sfc(values: Vec<Option<Vec<Vec<(f64, f64)>>>>) -> Robj {
+fn as_multiline_sfc(
+    values: Vec<Option<Vec<Vec<Coord>>>>,
+    dim: Dim,
+) -> std::result::Result<Robj, ParseError> {
     let mut n_empty = 0;
+    let mut bbox = Bbox::new();
     let list: Vec<Robj> = values
         .into_iter()
-        .map(|opt| match opt {
-            Some(multilinestring) => {
-                let lines: Vec<Robj> = multilinestring
-                    .into_iter()
-                    .map(|line| {
-                        let flat_coords: Vec<f64> =
-                            line.iter().flat_map(|(x, y)| vec![*x, *y]).collect();
-                        let matrix =
-                            RMatrix::new_matrix(line.len(), 2, |r, c| flat_coords[r * 2 + c]);
-                        Robj::from(matrix)
-                    })
-                    .collect();
-
-                let mut robj = Robj::from(lines);
-                robj.set_class(&["XY", "MULTILINESTRING", "sfg"]).unwrap();
-                robj
-            }
-            None => {
+        .map(|opt| {
+            let multilinestring = opt.unwrap_or_else(|| {
                 n_empty += 1;
-                let mut robj = Robj::from(List::from_values(Vec::<Robj>::new()));
-                robj.set_class(&["XY", "MULTILINESTRING", "sfg"]).unwrap();
-                robj
-            }
+                Vec::new()
+            });
+            multilinestring.iter().for_each(|line| bbox.extend(line));
+            as_sfg(
+                as_matrix_list(&multilinestring, dim),
+                "MULTILINESTRING",
+                dim,
+            )
         })
         .collect();
 
-    as_sfc(Robj::from(list), "multilinestring", n_empty)
+    as_sfc(Robj::from(list), "multilinestring", dim, n_empty, bbox)
 }
 
-fn as_multipolygon_sfc(values: Vec<Option<Vec<Vec<Vec<(f64, f64)>>>>>) -> Robj {
+fn as_multipolygon_sfc(
+    values: Vec<Option<Vec<Vec<Vec<Coord>>>>>,
+    dim: Dim,
+) -> std::result::Result<Robj, ParseError> {
     let mut n_empty = 0;
+    let mut bbox = Bbox::new();
     let list: Vec<Robj> = values
         .into_iter()
-        .map(|opt| match opt {
-            Some(multipolygon) => {
-                let exterior_list: Vec<Robj> = multipolygon
-                    .into_iter()
-                    .map(|polygon| {
-                        let rings: Vec<Robj> = polygon
-                            .into_iter()
-                            .map(|ring| {
-                                let flat_coords: Vec<f64> =
-                                    ring.iter().flat_map(|(x, y)| vec![*x, *y]).collect();
-                                let matrix = RMatrix::new_matrix(ring.len(), 2, |r, c| {
-                                    flat_coords[r * 2 + c]
-                                });
-                                Robj::from(matrix)
-                            })
-                            .collect();
-                        Robj::from(rings)
-                    })
-                    .collect();
-
-                let mut robj = Robj::from(exterior_list);
-                robj.set_class(&["XY", "MULTIPOLYGON", "sfg"]).unwrap();
-                robj
-            }
-            None => {
+        .map(|opt| {
+            let multipolygon = opt.unwrap_or_else(|| {
                 n_empty += 1;
-                let mut robj = Robj::from(List::from_values(Vec::<Robj>::new()));
-                robj.set_class(&["XY", "MULTIPOLYGON", "sfg"]).unwrap();
-                robj
-            }
+                Vec::new()
+            });
+            multipolygon
+                .iter()
+                .flatten()
+                .for_each(|ring| bbox.extend(ring));
+            let polygons: Vec<Robj> = multipolygon
+                .iter()
+                .map(|polygon| as_matrix_list(polygon, dim))
+                .collect();
+            as_sfg(Robj::from(polygons), "MULTIPOLYGON", dim)
         })
         .collect();
 
-    as_sfc(Robj::from(list), "multipolygon", n_empty)
+    as_sfc(Robj::from(list), "multipolygon", dim, n_empty, bbox)
 }
 
 fn as_location_list(
-    coords: Vec<Option<(f64, f64)>>,
-    addresses: Vec<Option<String>>,
-    cities: Vec<Option<String>>,
-    states: Vec<Option<String>>,
-    zips: Vec<Option<String>>,
-) -> Robj {
-    let geometry = as_point_sfc(coords);
+    location: LocationColumn,
+    geometry_class: GeometryClass,
+) -> std::result::Result<Robj, ParseError> {
+    let LocationColumn {
+        coords,
+        addresses,
+        cities,
+        states,
+        zips,
+    } = location;
+    let dim = Dim::XY;
+    let geometry = match geometry_class {
+        GeometryClass::Sfc => as_point_sfc(coords, dim)?,
+        wk => as_wk(
+            &coords,
+            wk,
+            |out, pt| wkb::write_point(out, *pt, dim),
+            |out, pt| wkt::write_point(out, *pt, dim),
+        ),
+    };
     let address = as_character(addresses);
     let city = as_character(cities);
     let state = as_character(states);
@@ -309,19 +652,159 @@ fn as_location_list(
         zip = zip
     );
 
-    location_list.into_robj()
+    Ok(location_list.into_robj())
 }
 
-fn empty_bbox() -> Robj {
-    let mut bbox =
-        Doubles::from_values([Rfloat::na(), Rfloat::na(), Rfloat::na(), Rfloat::na()]).into_robj();
+/// Build a wk `wk_wkb` list of raw vectors or a `wk_wkt` character vector,
+/// with missing geometries as `NULL` or `NA`.
+fn as_wk<T>(
+    values: &[Option<T>],
+    geometry_class: GeometryClass,
+    write_wkb: impl Fn(&mut Vec<u8>, &T),
+    write_wkt: impl Fn(&mut String, &T),
+) -> Robj {
+    let mut robj = match geometry_class {
+        GeometryClass::Wkt => {
+            let vec: Vec<Option<String>> = values
+                .iter()
+                .map(|opt| {
+                    opt.as_ref().map(|geom| {
+                        let mut text = String::new();
+                        write_wkt(&mut text, geom);
+                        text
+                    })
+                })
+                .collect();
+            let mut robj = as_character(vec);
+            robj.set_class(&["wk_wkt", "wk_vctr"]).unwrap();
+            robj
+        }
+        _ => {
+            let list: Vec<Robj> = values
+                .iter()
+                .map(|opt| match opt {
+                    Some(geom) => {
+                        let mut bytes = Vec::new();
+                        write_wkb(&mut bytes, geom);
+                        Raw::from_bytes(&bytes).into_robj()
+                    }
+                    None => r!(NULL),
+                })
+                .collect();
+            let mut robj = Robj::from(list);
+            robj.set_class(&["wk_wkb", "wk_vctr"]).unwrap();
+            robj
+        }
+    };
+    robj.set_attrib("crs", "EPSG:4326").unwrap();
+    robj
+}
+
+/// Extent of the coordinates of an sfc column, tracked while its geometries
+/// are built.
+struct Bbox {
+    xmin: f64,
+    ymin: f64,
+    xmax: f64,
+    ymax: f64,
+    z: Range,
+    m: Range,
+}
+
+impl Bbox {
+    fn new() -> Bbox {
+        Bbox {
+            xmin: f64::INFINITY,
+            ymin: f64::INFINITY,
+            xmax: f64::NEG_INFINITY,
+            ymax: f64::NEG_INFINITY,
+            z: Range::new(),
+            m: Range::new(),
+        }
+    }
+
+    fn add(&mut self, coord: Coord) {
+        if coord.x.is_nan() || coord.y.is_nan() {
+            return;
+        }
+        self.xmin = self.xmin.min(coord.x);
+        self.ymin = self.ymin.min(coord.y);
+        self.xmax = self.xmax.max(coord.x);
+        self.ymax = self.ymax.max(coord.y);
+        self.z.add(coord.z);
+        self.m.add(coord.m);
+    }
+
+    fn extend(&mut self, coords: &[Coord]) {
+        coords.iter().for_each(|coord| self.add(*coord));
+    }
+
+    /// A named `bbox` vector, all `NA` if there were no coordinates.
+    fn to_robj(&self, crs: &Robj) -> Robj {
+        let values = if self.xmin <= self.xmax {
+            [self.xmin, self.ymin, self.xmax, self.ymax].map(Rfloat::from)
+        } else {
+            [Rfloat::na(); 4]
+        };
+        let mut bbox = Doubles::from_values(values).into_robj();
+
+        bbox.set_names(&["xmin", "ymin", "xmax", "ymax"]).unwrap();
+        bbox.set_class(&["bbox"]).unwrap();
+        bbox.set_attrib("crs", crs).unwrap();
+        bbox
+    }
+}
 
-    bbox.set_names(&["xmin", "ymin", "xmax", "ymax"]).unwrap();
-    bbox
+/// The extent of the z or m ordinates of an sfc.
+struct Range {
+    min: f64,
+    max: f64,
 }
 
-pub fn as_sfc(mut list_col: Robj, geom_type: &str, n_empty: i32) -> Robj {
+impl Range {
+    fn new() -> Range {
+        Range {
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    fn add(&mut self, value: f64) {
+        if !value.is_nan() {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+    }
+
+    /// A `z_range` or `m_range` vector named after `ordinate`, all `NA` if
+    /// there were no values.
+    fn to_robj(&self, ordinate: &str, crs: &Robj) -> Robj {
+        let values = if self.min <= self.max {
+            [self.min, self.max].map(Rfloat::from)
+        } else {
+            [Rfloat::na(); 2]
+        };
+        let mut range = Doubles::from_values(values).into_robj();
+
+        range
+            .set_names(&[format!("{}min", ordinate), format!("{}max", ordinate)])
+            .unwrap();
+        range.set_class(&[format!("{}_range", ordinate)]).unwrap();
+        range.set_attrib("crs", crs).unwrap();
+        range
+    }
+}
+
+fn as_sfc(
+    mut list_col: Robj,
+    geom_type: &str,
+    dim: Dim,
+    n_empty: i32,
+    bbox: Bbox,
+) -> std::result::Result<Robj, ParseError> {
     let class_name = format!("sfc_{}", geom_type.to_uppercase());
+    let crs = R!("sf::st_crs(4326)")
+        .map_err(|_| ParseError::argument("sf is required for `geometry_class = \"sf\"`"))?;
 
     list_col
         .set_class(&[&class_name, "sfc"])
@@ -330,9 +813,19 @@ pub fn as_sfc(mut list_col: Robj, geom_type: &str, n_empty: i32) -> Robj {
         .unwrap()
         .set_attrib("n_empty", n_empty)
         .unwrap()
-        .set_attrib("bbox", empty_bbox())
-        .unwrap()
-        .set_attrib("crs", R!("sf::st_crs(4326)"))
+        .set_attrib("bbox", bbox.to_robj(&crs))
         .unwrap();
-    list_col
+    // sf only sets the z and m extents of geometries that have them
+    if dim.z {
+        list_col
+            .set_attrib("z_range", bbox.z.to_robj("z", &crs))
+            .unwrap();
+    }
+    if dim.m {
+        list_col
+            .set_attrib("m_range", bbox.m.to_robj("m", &crs))
+            .unwrap();
+    }
+    list_col.set_attrib("crs", crs).unwrap();
+    Ok(list_col)
 }
@@ -0,0 +1,1648 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use extendr_api::{list, r, Attributes, IntoRobj, List, Robj};
+use serde::de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde_json::Value;
+
+use crate::csv::Records;
+use crate::error::ParseError;
+use crate::infer::{infer_values, system_type};
+use crate::parse::*;
+use crate::process::{as_rlist, Column, LocationColumn, OutputOptions};
+use crate::repair::Issues;
+use crate::rows::{legacy_cell, Meta};
+use crate::time::Floating;
+
+impl Column {
+    /// Create an empty column for a Socrata datatype.
+    pub fn from_type(col_type: &str) -> Option<Column> {
+        let column = match col_type {
+            "boolean" => Column::Boolean(Vec::new()),
+            "number" => Column::Number(Vec::new()),
+            "fixed_timestamp" => Column::FixedTimestamp(Vec::new()),
+            "floating_timestamp" => Column::FloatingTimestamp(Vec::new()),
+            "text" | "row_version" => Column::Text(Vec::new()),
+            "row_identifier" => Column::RowId(Vec::new()),
+            "url" => Column::Url((Vec::new(), Vec::new())),
+            "photo" => Column::Photo(Vec::new()),
+            "document" => Column::Document(Vec::new()),
+            "checkbox" => Column::Checkbox(Vec::new()),
+            "money" => Column::Money(Vec::new()),
+            "percent" => Column::Percent(Vec::new()),
+            "date" => Column::Date(Vec::new()),
+            "calendar_date" => Column::CalendarDate(Vec::new()),
+            "html" => Column::Html(Vec::new()),
+            "email" => Column::Email(Vec::new()),
+            "phone" => Column::Phone((Vec::new(), Vec::new())),
+            "blob" => Column::Blob(Vec::new()),
+            "json" | "object" | "array" => Column::Json(Vec::new()),
+            "point" => Column::Point(Vec::new()),
+            "line" => Column::Line(Vec::new()),
+            "polygon" => Column::Polygon(Vec::new()),
+            "multipoint" => Column::MultiPoint(Vec::new()),
+            "multiline" => Column::MultiLine(Vec::new()),
+            "multipolygon" => Column::MultiPolygon(Vec::new()),
+            "location" => Column::Location(LocationColumn::default()),
+            _ => return None,
+        };
+        Some(column)
+    }
+
+    /// Whether the column holds GeoJSON geometries.
+    pub fn is_geometry(&self) -> bool {
+        matches!(
+            self,
+            Column::Point(_)
+                | Column::Line(_)
+                | Column::Polygon(_)
+                | Column::MultiPoint(_)
+                | Column::MultiLine(_)
+                | Column::MultiPolygon(_)
+        )
+    }
+
+    /// Parse a single cell and append it to the column.
+    ///
+    /// Fails only for floating timestamps that must not fall in a daylight
+    /// saving time fold or gap.
+    pub fn push(
+        &mut self,
+        val: Option<&Value>,
+        floating: Floating,
+        meta_url: &str,
+    ) -> Result<(), String> {
+        match self {
+            Column::Boolean(vec) => {
+                vec.push(parse_boolean(val));
+            }
+            Column::Number(vec) => {
+                vec.push(parse_number(val));
+            }
+            Column::FixedTimestamp(vec) => {
+                vec.push(parse_fixed_timestamp(val));
+            }
+            Column::FloatingTimestamp(vec) => {
+                vec.push(parse_floating_timestamp(val, floating)?);
+            }
+            Column::Text(vec) | Column::RowId(vec) => {
+                vec.push(parse_text(val));
+            }
+            Column::Url((urls, descs)) => {
+                let (url_val, desc_val) = parse_url(val);
+                urls.push(url_val);
+                descs.push(desc_val);
+            }
+            Column::Photo(vec) => {
+                vec.push(parse_photo(val, meta_url));
+            }
+            Column::Document(vec) => {
+                vec.push(parse_document(val, meta_url));
+            }
+            Column::Checkbox(vec) => {
+                vec.push(parse_checkbox(val));
+            }
+            Column::Money(vec) => {
+                vec.push(parse_money(val));
+            }
+            Column::Percent(vec) => {
+                vec.push(parse_percent(val));
+            }
+            Column::Date(vec) => {
+                vec.push(parse_date(val));
+            }
+            Column::CalendarDate(vec) => {
+                vec.push(parse_calendar_date(val, floating)?);
+            }
+            Column::Html(vec) => {
+                vec.push(parse_html(val));
+            }
+            Column::Email(vec) => {
+                vec.push(parse_email(val));
+            }
+            Column::Phone((numbers, types)) => {
+                let (number_val, type_val) = parse_phone(val);
+                numbers.push(number_val);
+                types.push(type_val);
+            }
+            Column::Blob(vec) => {
+                vec.push(parse_blob(val, meta_url));
+            }
+            Column::Json(vec) => {
+                vec.push(parse_json(val));
+            }
+            Column::Point(vec) => {
+                vec.push(parse_point(val));
+            }
+            Column::Line(vec) => {
+                vec.push(parse_line(val));
+            }
+            Column::Polygon(vec) => {
+                vec.push(parse_polygon(val));
+            }
+            Column::MultiPoint(vec) => {
+                vec.push(parse_multipoint(val));
+            }
+            Column::MultiLine(vec) => {
+                vec.push(parse_multiline(val));
+            }
+            Column::MultiPolygon(vec) => {
+                vec.push(parse_multipolygon(val));
+            }
+            Column::Location(location) => {
+                let parsed = parse_location(val);
+                location.coords.push(parsed.coord);
+                location.addresses.push(parsed.address);
+                location.cities.push(parsed.city);
+                location.states.push(parsed.state);
+                location.zips.push(parsed.zip);
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of rows in the column.
+    pub fn len(&self) -> usize {
+        match self {
+            Column::Boolean(v) | Column::Checkbox(v) => v.len(),
+            Column::Number(v) | Column::Money(v) | Column::Percent(v) => v.len(),
+            Column::FixedTimestamp(v)
+            | Column::FloatingTimestamp(v)
+            | Column::Date(v)
+            | Column::CalendarDate(v) => v.len(),
+            Column::Text(v)
+            | Column::RowId(v)
+            | Column::Photo(v)
+            | Column::Document(v)
+            | Column::Html(v)
+            | Column::Email(v)
+            | Column::Blob(v)
+            | Column::Json(v) => v.len(),
+            Column::Url((v, _)) | Column::Phone((v, _)) => v.len(),
+            Column::Point(v) => v.len(),
+            Column::Line(v) | Column::MultiPoint(v) => v.len(),
+            Column::Polygon(v) | Column::MultiLine(v) => v.len(),
+            Column::MultiPolygon(v) => v.len(),
+            Column::Location(location) => location.coords.len(),
+        }
+    }
+
+    /// Shorten the column to its first `len` rows.
+    pub fn truncate(&mut self, len: usize) {
+        match self {
+            Column::Boolean(v) | Column::Checkbox(v) => v.truncate(len),
+            Column::Number(v) | Column::Money(v) | Column::Percent(v) => v.truncate(len),
+            Column::FixedTimestamp(v)
+            | Column::FloatingTimestamp(v)
+            | Column::Date(v)
+            | Column::CalendarDate(v) => v.truncate(len),
+            Column::Text(v)
+            | Column::RowId(v)
+            | Column::Photo(v)
+            | Column::Document(v)
+            | Column::Html(v)
+            | Column::Email(v)
+            | Column::Blob(v)
+            | Column::Json(v) => v.truncate(len),
+            Column::Url((a, b)) | Column::Phone((a, b)) => {
+                a.truncate(len);
+                b.truncate(len);
+            }
+            Column::Point(v) => v.truncate(len),
+            Column::Line(v) | Column::MultiPoint(v) => v.truncate(len),
+            Column::Polygon(v) | Column::MultiLine(v) => v.truncate(len),
+            Column::MultiPolygon(v) => v.truncate(len),
+            Column::Location(location) => {
+                location.coords.truncate(len);
+                location.addresses.truncate(len);
+                location.cities.truncate(len);
+                location.states.truncate(len);
+                location.zips.truncate(len);
+            }
+        }
+    }
+
+    /// Whether the last value pushed was parsed as missing.
+    pub fn last_is_na(&self) -> bool {
+        fn is_na<T>(v: &[Option<T>]) -> bool {
+            matches!(v.last(), Some(None))
+        }
+
+        match self {
+            Column::Boolean(v) | Column::Checkbox(v) => is_na(v),
+            Column::Number(v) | Column::Money(v) | Column::Percent(v) => is_na(v),
+            Column::FixedTimestamp(v)
+            | Column::FloatingTimestamp(v)
+            | Column::Date(v)
+            | Column::CalendarDate(v) => is_na(v),
+            Column::Text(v)
+            | Column::RowId(v)
+            | Column::Photo(v)
+            | Column::Document(v)
+            | Column::Html(v)
+            | Column::Email(v)
+            | Column::Blob(v)
+            | Column::Json(v) => is_na(v),
+            Column::Url((a, b)) | Column::Phone((a, b)) => is_na(a) && is_na(b),
+            Column::Point(v) => is_na(v),
+            Column::Line(v) | Column::MultiPoint(v) => is_na(v),
+            Column::Polygon(v) | Column::MultiLine(v) => is_na(v),
+            Column::MultiPolygon(v) => is_na(v),
+            Column::Location(location) => {
+                is_na(&location.coords)
+                    && is_na(&location.addresses)
+                    && is_na(&location.cities)
+                    && is_na(&location.states)
+                    && is_na(&location.zips)
+            }
+        }
+    }
+
+    /// Move all values of `other` onto the end of this column.
+    ///
+    /// Both columns must have been created from the same datatype.
+    pub fn append(&mut self, other: Column) {
+        match (self, other) {
+            (Column::Boolean(a), Column::Boolean(b)) => a.extend(b),
+            (Column::Number(a), Column::Number(b)) => a.extend(b),
+            (Column::FixedTimestamp(a), Column::FixedTimestamp(b)) => a.extend(b),
+            (Column::FloatingTimestamp(a), Column::FloatingTimestamp(b)) => a.extend(b),
+            (Column::Text(a), Column::Text(b)) => a.extend(b),
+            (Column::RowId(a), Column::RowId(b)) => a.extend(b),
+            (Column::Url((a1, a2)), Column::Url((b1, b2))) => {
+                a1.extend(b1);
+                a2.extend(b2);
+            }
+            (Column::Photo(a), Column::Photo(b)) => a.extend(b),
+            (Column::Document(a), Column::Document(b)) => a.extend(b),
+            (Column::Checkbox(a), Column::Checkbox(b)) => a.extend(b),
+            (Column::Money(a), Column::Money(b)) => a.extend(b),
+            (Column::Percent(a), Column::Percent(b)) => a.extend(b),
+            (Column::Date(a), Column::Date(b)) => a.extend(b),
+            (Column::CalendarDate(a), Column::CalendarDate(b)) => a.extend(b),
+            (Column::Html(a), Column::Html(b)) => a.extend(b),
+            (Column::Email(a), Column::Email(b)) => a.extend(b),
+            (Column::Phone((a1, a2)), Column::Phone((b1, b2))) => {
+                a1.extend(b1);
+                a2.extend(b2);
+            }
+            (Column::Blob(a), Column::Blob(b)) => a.extend(b),
+            (Column::Json(a), Column::Json(b)) => a.extend(b),
+            (Column::Point(a), Column::Point(b)) => a.extend(b),
+            (Column::Line(a), Column::Line(b)) => a.extend(b),
+            (Column::Polygon(a), Column::Polygon(b)) => a.extend(b),
+            (Column::MultiPoint(a), Column::MultiPoint(b)) => a.extend(b),
+            (Column::MultiLine(a), Column::MultiLine(b)) => a.extend(b),
+            (Column::MultiPolygon(a), Column::MultiPolygon(b)) => a.extend(b),
+            (Column::Location(a), Column::Location(b)) => {
+                a.coords.extend(b.coords);
+                a.addresses.extend(b.addresses);
+                a.cities.extend(b.cities);
+                a.states.extend(b.states);
+                a.zips.extend(b.zips);
+            }
+            _ => unreachable!("Cannot append columns of different types"),
+        }
+    }
+}
+
+/// Maximum number of problems kept per column. Further problems are only
+/// counted.
+const MAX_PROBLEMS: usize = 1000;
+
+/// A non-null cell that could not be parsed as its column's type.
+pub struct Problem {
+    /// 0-based row within the parsed rows.
+    pub row: usize,
+    pub column: usize,
+    pub value: String,
+}
+
+/// Parse problems, bounded to [`MAX_PROBLEMS`] per column.
+pub struct Problems {
+    pub list: Vec<Problem>,
+    stored: Vec<usize>,
+    counts: Vec<usize>,
+    // the row of the last problem counted in each column
+    last_rows: Vec<Option<usize>>,
+}
+
+impl Problems {
+    fn new(n_columns: usize) -> Problems {
+        Problems {
+            list: Vec::new(),
+            stored: vec![0; n_columns],
+            counts: vec![0; n_columns],
+            last_rows: vec![None; n_columns],
+        }
+    }
+
+    fn push(&mut self, problem: Problem) {
+        let column = problem.column;
+        self.counts[column] += 1;
+        self.last_rows[column] = Some(problem.row);
+        if self.stored[column] < MAX_PROBLEMS {
+            self.stored[column] += 1;
+            self.list.push(problem);
+        }
+    }
+
+    /// Append problems found in rows that follow `n_rows` existing rows.
+    fn append(&mut self, other: Problems, n_rows: usize) {
+        for mut problem in other.list {
+            let column = problem.column;
+            if self.stored[column] < MAX_PROBLEMS {
+                problem.row += n_rows;
+                self.stored[column] += 1;
+                self.list.push(problem);
+            }
+        }
+        for (count, other) in self.counts.iter_mut().zip(other.counts) {
+            *count += other;
+        }
+    }
+
+    /// Forget the problem of a cell that is being replaced, if it had one.
+    fn remove(&mut self, row: usize, column: usize) {
+        if self.last_rows[column] != Some(row) {
+            return;
+        }
+        self.last_rows[column] = None;
+        self.counts[column] -= 1;
+        let stored = self
+            .list
+            .iter()
+            .rposition(|problem| problem.column == column && problem.row == row);
+        if let Some(k) = stored {
+            self.list.remove(k);
+            self.stored[column] -= 1;
+        }
+    }
+
+    fn add_column(&mut self) {
+        self.stored.push(0);
+        self.counts.push(0);
+        self.last_rows.push(None);
+    }
+
+    /// Total number of problems, including those not kept.
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+}
+
+/// What was repaired in each row of the columns whose geometries are
+/// repaired.
+pub struct Validity {
+    // `None` for columns that are not repaired, and for missing geometries
+    columns: Vec<Option<Vec<Option<Issues>>>>,
+}
+
+impl Validity {
+    fn new(columns: &[Column], repair: bool) -> Validity {
+        Validity {
+            columns: columns
+                .iter()
+                .map(|column| (repair && column.is_repairable()).then(Vec::new))
+                .collect(),
+        }
+    }
+
+    fn append(&mut self, other: Validity) {
+        for (rows, other) in self.columns.iter_mut().zip(other.columns) {
+            if let (Some(rows), Some(other)) = (rows, other) {
+                rows.extend(other);
+            }
+        }
+    }
+}
+
+/// Fields of the rows that are not in the schema, kept as raw values until
+/// their type can be inferred from every page.
+#[derive(Default)]
+pub struct ExtraFields {
+    names: Vec<String>,
+    lookup: HashMap<String, usize>,
+    // the non-null values of each field, with their 0-based row
+    cells: Vec<Vec<(usize, Value)>>,
+}
+
+impl ExtraFields {
+    fn index(&mut self, name: &str) -> usize {
+        if let Some(&i) = self.lookup.get(name) {
+            return i;
+        }
+        self.names.push(name.to_string());
+        self.cells.push(Vec::new());
+        self.lookup.insert(name.to_string(), self.names.len() - 1);
+        self.names.len() - 1
+    }
+
+    /// Keep the value of a field, replacing any earlier value in the same row.
+    fn push(&mut self, name: &str, row: usize, val: Value) {
+        let i = self.index(name);
+        if matches!(self.cells[i].last(), Some((r, _)) if *r == row) {
+            self.cells[i].pop();
+        }
+        if !val.is_null() {
+            self.cells[i].push((row, val));
+        }
+    }
+
+    /// Append fields found in rows that follow `n_rows` existing rows.
+    fn append(&mut self, other: ExtraFields, n_rows: usize) {
+        for (name, cells) in other.names.iter().zip(other.cells) {
+            let i = self.index(name);
+            self.cells[i].extend(cells.into_iter().map(|(row, val)| (row + n_rows, val)));
+        }
+    }
+}
+
+/// Columns parsed from one or more pages, with their parse problems.
+pub struct Chunk {
+    pub columns: Vec<Column>,
+    pub problems: Problems,
+    pub validity: Validity,
+    pub extra: Option<ExtraFields>,
+    pub n_rows: usize,
+}
+
+/// The format of the pages pushed to a [`Reader`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    /// A JSON array of row objects, from `.json` endpoints.
+    Json,
+    /// A header row of field names followed by one row per record, from
+    /// `.csv` endpoints.
+    Csv,
+    /// A GeoJSON FeatureCollection, from `.geojson` endpoints, with the
+    /// geometry of the first geometry column in each feature's `geometry`
+    /// and the other columns as its `properties`.
+    GeoJson,
+    /// A legacy `rows.json` export, whose `meta.view.columns` give the field
+    /// of each position of the row arrays in `data`.
+    RowsJson,
+    /// Newline-delimited JSON, with one row object per line.
+    Ndjson,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            "geojson" => Some(Format::GeoJson),
+            "ndjson" => Some(Format::Ndjson),
+            _ => None,
+        }
+    }
+}
+
+/// Builds typed columns from Socrata JSON, GeoJSON or CSV pages without
+/// materializing rows.
+///
+/// Each JSON page is deserialized with a [`DeserializeSeed`] that walks the
+/// row objects field by field, so only the value of the current cell is ever
+/// held in memory as a [`Value`]. GeoJSON features are walked the same way,
+/// with their `properties` read as row objects. CSV pages are read one record at a time,
+/// with each field handed to the same cell parsers as a JSON string.
+pub struct Reader {
+    pub col_names: Vec<String>,
+    pub col_types: Vec<String>,
+    pub columns: Vec<Column>,
+    // the first column of each field name
+    lookup: HashMap<String, usize>,
+    // the next column with the same name, for names repeated in the schema
+    same_name: Vec<Option<usize>>,
+    floating: Floating,
+    meta_url: String,
+    pub unknown_types: Vec<(String, String)>,
+    pub problems: Problems,
+    repair: bool,
+    validity: Validity,
+    format: Format,
+    // fields outside the schema, if they are kept
+    extra: Option<ExtraFields>,
+    n_pages: usize,
+    // rows parsed since the last chunk was taken
+    n_rows: usize,
+    // column whose value is currently being deserialized, used to locate errors
+    current: Option<usize>,
+}
+
+impl Reader {
+    pub fn new(
+        col_names: Vec<String>,
+        col_types: Vec<String>,
+        floating: Floating,
+        meta_url: &str,
+        repair: bool,
+        format: Format,
+        extra_fields: bool,
+    ) -> Reader {
+        let col_types: Vec<String> = col_names
+            .iter()
+            .zip(col_types)
+            .map(|(name, ty)| system_type(name).map_or(ty, str::to_string))
+            .collect();
+        let (columns, unknown_types) = build_columns(&col_names, &col_types);
+        let validity = Validity::new(&columns, repair);
+        let mut lookup = HashMap::new();
+        let mut same_name = vec![None; col_names.len()];
+        for (i, name) in col_names.iter().enumerate().rev() {
+            same_name[i] = lookup.insert(name.clone(), i);
+        }
+
+        let problems = Problems::new(columns.len());
+
+        Reader {
+            col_names,
+            col_types,
+            columns,
+            lookup,
+            same_name,
+            floating,
+            meta_url: meta_url.to_string(),
+            unknown_types,
+            problems,
+            repair,
+            validity,
+            format,
+            extra: extra_fields.then(ExtraFields::default),
+            n_pages: 0,
+            n_rows: 0,
+            current: None,
+        }
+    }
+
+    /// Parse a raw page and append its rows to the columns.
+    pub fn push_page(&mut self, bytes: &[u8]) -> Result<(), ParseError> {
+        self.n_pages += 1;
+        self.current = None;
+
+        match self.format {
+            Format::Json => self.push_json(bytes),
+            Format::Csv => self.push_csv(bytes),
+            Format::GeoJson => self.push_geojson(bytes),
+            Format::RowsJson => self.push_rows_json(bytes),
+            Format::Ndjson => self.push_ndjson(bytes),
+        }
+    }
+
+    /// Parse a JSON array of row objects.
+    fn push_json(&mut self, bytes: &[u8]) -> Result<(), ParseError> {
+        let mut de = serde_json::Deserializer::from_slice(bytes);
+        PageSeed { reader: self }
+            .deserialize(&mut de)
+            .and_then(|_| de.end())
+            .map_err(|err| {
+                let column = self
+                    .current
+                    .map(|i| (self.col_names[i].clone(), self.col_types[i].clone()));
+                ParseError::json(self.n_pages, bytes, column, err)
+            })
+    }
+
+    /// Parse a GeoJSON FeatureCollection.
+    fn push_geojson(&mut self, bytes: &[u8]) -> Result<(), ParseError> {
+        let mut de = serde_json::Deserializer::from_slice(bytes);
+        CollectionSeed { reader: self }
+            .deserialize(&mut de)
+            .and_then(|_| de.end())
+            .map_err(|err| {
+                let column = self
+                    .current
+                    .map(|i| (self.col_names[i].clone(), self.col_types[i].clone()));
+                ParseError::json(self.n_pages, bytes, column, err)
+            })
+    }
+
+    /// Parse newline-delimited row objects, skipping blank lines.
+    fn push_ndjson(&mut self, bytes: &[u8]) -> Result<(), ParseError> {
+        let mut seen = vec![false; self.columns.len()];
+        let mut start = 0;
+        for line in bytes.split(|&b| b == b'\n') {
+            let line_start = start;
+            start += line.len() + 1;
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+
+            let mut de = serde_json::Deserializer::from_slice(line);
+            RowSeed {
+                reader: self,
+                seen: &mut seen,
+            }
+            .deserialize(&mut de)
+            .and_then(|_| de.end())
+            .map_err(|err| ParseError::Json {
+                page: self.n_pages,
+                offset: line_start + err.column().saturating_sub(1),
+                column: self
+                    .current
+                    .map(|i| (self.col_names[i].clone(), self.col_types[i].clone())),
+                message: err.to_string(),
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Parse a legacy rows.json export.
+    fn push_rows_json(&mut self, bytes: &[u8]) -> Result<(), ParseError> {
+        let mut de = serde_json::Deserializer::from_slice(bytes);
+        RowsFileSeed { reader: self }
+            .deserialize(&mut de)
+            .and_then(|_| de.end())
+            .map_err(|err| {
+                let column = self
+                    .current
+                    .map(|i| (self.col_names[i].clone(), self.col_types[i].clone()));
+                ParseError::json(self.n_pages, bytes, column, err)
+            })
+    }
+
+    /// Parse a CSV page, whose header row gives the field of each column.
+    /// Empty fields are missing values.
+    fn push_csv(&mut self, bytes: &[u8]) -> Result<(), ParseError> {
+        let text = std::str::from_utf8(bytes)
+            .map_err(|err| self.csv_error(err.valid_up_to(), "invalid UTF-8".to_string()))?;
+        let mut records = Records::new(text);
+        let mut fields = Vec::new();
+
+        let offset = records.offset();
+        let header: Vec<Option<usize>> = match records.next_record(&mut fields) {
+            Ok(true) => fields
+                .iter()
+                .map(|name| self.lookup.get(name).copied())
+                .collect(),
+            Ok(false) => return Ok(()),
+            Err(message) => return Err(self.csv_error(offset, message)),
+        };
+        let extra_names: Vec<Option<String>> = match self.extra {
+            Some(_) => header
+                .iter()
+                .zip(fields.drain(..))
+                .map(|(i, name)| i.is_none().then_some(name))
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let mut seen = vec![false; self.columns.len()];
+        loop {
+            let offset = records.offset();
+            match records.next_record(&mut fields) {
+                Ok(true) => {}
+                Ok(false) => return Ok(()),
+                Err(message) => return Err(self.csv_error(offset, message)),
+            }
+            if fields.len() != header.len() {
+                let message = format!(
+                    "record has {} fields but the header has {}",
+                    fields.len(),
+                    header.len()
+                );
+                return Err(self.csv_error(offset, message));
+            }
+
+            seen.iter_mut().for_each(|s| *s = false);
+            for (j, (i, field)) in header.iter().zip(fields.drain(..)).enumerate() {
+                let i = match *i {
+                    Some(i) => i,
+                    None => {
+                        let name = extra_names.get(j).and_then(Option::as_deref);
+                        if let (Some(extra), Some(name)) = (self.extra.as_mut(), name) {
+                            let val = match field.is_empty() {
+                                true => Value::Null,
+                                false => Value::String(field),
+                            };
+                            extra.push(name, self.n_rows, val);
+                        }
+                        continue;
+                    }
+                };
+                let val = (!field.is_empty()).then_some(Value::String(field));
+                self.push_field(i, val.as_ref(), &mut seen)
+                    .map_err(|message| self.csv_error(offset, message))?;
+            }
+            self.current = None;
+            self.finish_row(&seen)
+                .map_err(|message| self.csv_error(offset, message))?;
+        }
+    }
+
+    /// A CSV error at byte `offset` of the current page, in the column being
+    /// parsed if any.
+    fn csv_error(&self, offset: usize, message: String) -> ParseError {
+        ParseError::Csv {
+            page: self.n_pages,
+            offset,
+            column: self
+                .current
+                .map(|i| (self.col_names[i].clone(), self.col_types[i].clone())),
+            message,
+        }
+    }
+
+    /// Parse several pages, spreading them over up to `n_threads` threads.
+    ///
+    /// Pages are split into contiguous chunks, each parsed by its own reader,
+    /// and the resulting columns are appended in page order.
+    pub fn push_pages(&mut self, pages: &[&[u8]], n_threads: usize) -> Result<(), ParseError> {
+        if n_threads <= 1 || pages.len() <= 1 {
+            return pages.iter().try_for_each(|bytes| self.push_page(bytes));
+        }
+
+        let chunk_size = (pages.len() + n_threads - 1) / n_threads;
+        let results: Vec<Result<Reader, ParseError>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = pages
+                .chunks(chunk_size)
+                .enumerate()
+                .map(|(i, chunk)| {
+                    let mut worker = self.fork(self.n_pages + i * chunk_size);
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .try_for_each(|bytes| worker.push_page(bytes))
+                            .map(|_| worker)
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap_or(Err(ParseError::Thread)))
+                .collect()
+        });
+
+        for result in results {
+            let mut worker = result?;
+            self.n_pages = worker.n_pages;
+            self.append_chunk(worker.take_chunk());
+        }
+        Ok(())
+    }
+
+    /// Parse page number `page` on its own, returning its rows.
+    pub fn parse_page(&mut self, page: usize, bytes: &[u8]) -> Result<Chunk, ParseError> {
+        self.n_pages = page - 1;
+        let result = self.push_page(bytes);
+        let chunk = self.take_chunk();
+        result.map(|_| chunk)
+    }
+
+    /// Number of pages pushed so far.
+    pub fn n_pages(&self) -> usize {
+        self.n_pages
+    }
+
+    /// Number of rows parsed since the last chunk was taken.
+    pub fn n_rows(&self) -> usize {
+        self.n_rows
+    }
+
+    /// Take the rows parsed so far, leaving empty columns in their place.
+    pub fn take_chunk(&mut self) -> Chunk {
+        let (columns, _) = build_columns(&self.col_names, &self.col_types);
+        let validity = Validity::new(&columns, self.repair);
+        Chunk {
+            columns: std::mem::replace(&mut self.columns, columns),
+            problems: std::mem::replace(&mut self.problems, Problems::new(self.col_names.len())),
+            validity: std::mem::replace(&mut self.validity, validity),
+            extra: self.extra.as_mut().map(std::mem::take),
+            n_rows: std::mem::replace(&mut self.n_rows, 0),
+        }
+    }
+
+    /// Append rows parsed by another reader with the same schema.
+    pub fn append_chunk(&mut self, chunk: Chunk) {
+        let n_rows = self.n_rows;
+        for (column, other) in self.columns.iter_mut().zip(chunk.columns) {
+            column.append(other);
+        }
+        self.problems.append(chunk.problems, n_rows);
+        self.validity.append(chunk.validity);
+        if let (Some(extra), Some(other)) = (self.extra.as_mut(), chunk.extra) {
+            extra.append(other, n_rows);
+        }
+        self.n_rows += chunk.n_rows;
+    }
+
+    /// Append the fields outside the schema as columns of the type inferred
+    /// from their values, returning their names and types.
+    pub fn add_extra_columns(&mut self) -> Vec<(String, String)> {
+        let extra = match self.extra.take() {
+            Some(extra) => extra,
+            None => return Vec::new(),
+        };
+
+        let mut added = Vec::new();
+        for (name, cells) in extra.names.into_iter().zip(extra.cells) {
+            let ty = system_type(&name)
+                .or_else(|| infer_values(cells.iter().map(|(_, val)| val)))
+                .unwrap_or("text");
+            let i = self.columns.len();
+            self.columns
+                .push(Column::from_type(ty).unwrap_or_else(|| Column::Text(Vec::new())));
+            self.col_names.push(name.clone());
+            self.col_types.push(ty.to_string());
+            self.problems.add_column();
+            self.validity.columns.push(None);
+            self.same_name.push(None);
+
+            let mut cells = cells.into_iter().peekable();
+            for row in 0..self.n_rows {
+                let val = cells.next_if(|(r, _)| *r == row).map(|(_, cell)| cell);
+                if self.push_cell(i, val.as_ref()).is_err() {
+                    // e.g. a floating timestamp in a fold, with `ambiguous = "error"`
+                    self.push_cell(i, None).ok();
+                    self.problems.push(Problem {
+                        row,
+                        column: i,
+                        value: val.map_or_else(String::new, |val| match val {
+                            Value::String(s) => s,
+                            val => val.to_string(),
+                        }),
+                    });
+                }
+            }
+            added.push((name, ty.to_string()));
+        }
+        added
+    }
+
+    /// How floating timestamps are resolved.
+    pub fn floating(&self) -> Floating {
+        self.floating
+    }
+
+    /// Convert the parsed columns into an R list.
+    ///
+    /// Columns whose type was not recognized are listed in the
+    /// `unknown_types` attribute as a named character vector, and cells that
+    /// could not be parsed, or timestamps that do not fit the requested
+    /// nanotime class, in the `problems` attribute. If geometries were
+    /// repaired, the `validity` attribute holds what was repaired in each row.
+    /// Fields outside the schema that were kept are added as columns and
+    /// listed with their inferred types in the `extra_fields` attribute.
+    pub fn finish(mut self, options: &OutputOptions) -> Result<Robj, ParseError> {
+        let extra = self.add_extra_columns();
+        for (i, column) in self.columns.iter().enumerate() {
+            for (row, value) in options.out_of_range(&self.col_names[i], column, self.floating) {
+                self.problems.push(Problem {
+                    row,
+                    column: i,
+                    value,
+                });
+            }
+        }
+        let problems = self.problems_robj();
+        let validity = self.validity_robj();
+        let mut rlist = as_rlist(self.col_names, self.columns, self.floating, options)?.into_robj();
+        if let Some(problems) = problems {
+            rlist.set_attrib("problems", problems).unwrap();
+        }
+        if let Some(validity) = validity {
+            rlist.set_attrib("validity", validity).unwrap();
+        }
+        if !self.unknown_types.is_empty() {
+            let (names, types): (Vec<String>, Vec<String>) =
+                self.unknown_types.into_iter().unzip();
+            let mut unknown = r!(types);
+            unknown.set_names(names).unwrap();
+            rlist.set_attrib("unknown_types", unknown).unwrap();
+        }
+        if !extra.is_empty() {
+            let (names, types): (Vec<String>, Vec<String>) = extra.into_iter().unzip();
+            let mut extra = r!(types);
+            extra.set_names(names).unwrap();
+            rlist.set_attrib("extra_fields", extra).unwrap();
+        }
+        Ok(rlist)
+    }
+
+    /// Parse problems as a list with the 1-based `row`, the `column` name, the
+    /// `expected` type and the `actual` value. The `total` attribute counts
+    /// problems beyond those kept.
+    fn problems_robj(&self) -> Option<Robj> {
+        let list = &self.problems.list;
+        if list.is_empty() {
+            return None;
+        }
+
+        let mut problems = List::from_names_and_values(
+            ["row", "column", "expected", "actual"],
+            [
+                r!(list.iter().map(|p| (p.row + 1) as i32).collect::<Vec<_>>()),
+                r!(list
+                    .iter()
+                    .map(|p| self.col_names[p.column].as_str())
+                    .collect::<Vec<_>>()),
+                r!(list
+                    .iter()
+                    .map(|p| self.col_types[p.column].as_str())
+                    .collect::<Vec<_>>()),
+                r!(list.iter().map(|p| p.value.as_str()).collect::<Vec<_>>()),
+            ],
+        )
+        .unwrap()
+        .into_robj();
+        problems
+            .set_attrib("total", r!(self.problems.total() as f64))
+            .unwrap();
+        Some(problems)
+    }
+
+    /// Repairs as a named list with, for each repaired column, a list of the
+    /// logical `valid` flag and the `reason` of each row, both `NA` for
+    /// missing geometries.
+    fn validity_robj(&self) -> Option<Robj> {
+        let (names, values): (Vec<&str>, Vec<Robj>) = self
+            .col_names
+            .iter()
+            .zip(&self.validity.columns)
+            .filter_map(|(name, rows)| {
+                let rows = rows.as_ref()?;
+                let valid: Vec<Option<bool>> = rows
+                    .iter()
+                    .map(|issues| issues.map(Issues::is_empty))
+                    .collect();
+                let reason: Vec<Option<String>> = rows
+                    .iter()
+                    .map(|issues| issues.and_then(Issues::reason))
+                    .collect();
+                let value = list!(valid = valid, reason = reason).into_robj();
+                Some((name.as_str(), value))
+            })
+            .unzip();
+        if names.is_empty() {
+            return None;
+        }
+        Some(List::from_names_and_values(names, values).unwrap().into_robj())
+    }
+
+    /// Create an empty reader with the same schema, starting at page `n_pages`.
+    pub fn fork(&self, n_pages: usize) -> Reader {
+        let (columns, _) = build_columns(&self.col_names, &self.col_types);
+        let validity = Validity::new(&columns, self.repair);
+        Reader {
+            col_names: self.col_names.clone(),
+            col_types: self.col_types.clone(),
+            columns,
+            lookup: self.lookup.clone(),
+            same_name: self.same_name.clone(),
+            floating: self.floating,
+            meta_url: self.meta_url.clone(),
+            unknown_types: Vec::new(),
+            problems: Problems::new(self.col_names.len()),
+            repair: self.repair,
+            validity,
+            format: self.format,
+            extra: self.extra.as_ref().map(|_| ExtraFields::default()),
+            n_pages,
+            n_rows: 0,
+            current: None,
+        }
+    }
+
+    /// Push a missing value to every column not in `seen`, ending the row.
+    fn finish_row(&mut self, seen: &[bool]) -> Result<(), String> {
+        for (i, seen) in seen.iter().enumerate() {
+            if !seen {
+                self.push_cell(i, None)?;
+            }
+        }
+        self.n_rows += 1;
+        Ok(())
+    }
+
+    /// Push the value of a field to column `i` and any later column of the
+    /// same name, replacing the value of a field repeated within the row.
+    fn push_field(
+        &mut self,
+        i: usize,
+        val: Option<&Value>,
+        seen: &mut [bool],
+    ) -> Result<(), String> {
+        let mut next = Some(i);
+        while let Some(i) = next {
+            if seen[i] {
+                self.pop_cell(i);
+            }
+            self.current = Some(i);
+            self.push_cell(i, val)?;
+            seen[i] = true;
+            next = self.same_name[i];
+        }
+        Ok(())
+    }
+
+    /// Remove the last value of column `i`, with its repairs and problem.
+    fn pop_cell(&mut self, i: usize) {
+        let row = self.columns[i].len() - 1;
+        self.columns[i].truncate(row);
+        if let Some(rows) = self.validity.columns[i].as_mut() {
+            rows.pop();
+        }
+        self.problems.remove(row, i);
+    }
+
+    fn push_cell(&mut self, i: usize, val: Option<&Value>) -> Result<(), String> {
+        let column = &mut self.columns[i];
+        column.push(val, self.floating, &self.meta_url)?;
+        if let Some(rows) = self.validity.columns[i].as_mut() {
+            rows.push(column.repair_last());
+        }
+
+        // a non-null value that parsed as missing could not be coerced
+        if let Some(val) = val.filter(|val| !val.is_null()) {
+            if column.last_is_na() {
+                self.problems.push(Problem {
+                    row: column.len() - 1,
+                    column: i,
+                    value: match val {
+                        Value::String(s) => s.clone(),
+                        _ => val.to_string(),
+                    },
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Create empty columns for a schema, returning any `(name, type)` pairs whose
+/// type is not recognized.
+fn build_columns(
+    col_names: &[String],
+    col_types: &[String],
+) -> (Vec<Column>, Vec<(String, String)>) {
+    let mut unknown_types = Vec::new();
+    let columns = col_names
+        .iter()
+        .zip(col_types.iter())
+        .map(|(name, ty)| {
+            Column::from_type(ty).unwrap_or_else(|| {
+                // fall back to text so that new Socrata types are still readable
+                unknown_types.push((name.clone(), ty.clone()));
+                Column::Text(Vec::new())
+            })
+        })
+        .collect();
+    (columns, unknown_types)
+}
+
+struct PageSeed<'a> {
+    reader: &'a mut Reader,
+}
+
+impl<'de> DeserializeSeed<'de> for PageSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for PageSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON array of row objects")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<(), A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut seen = vec![false; self.reader.columns.len()];
+        while seq
+            .next_element_seed(RowSeed {
+                reader: self.reader,
+                seen: &mut seen,
+            })?
+            .is_some()
+        {}
+        Ok(())
+    }
+}
+
+struct RowSeed<'a> {
+    reader: &'a mut Reader,
+    seen: &'a mut [bool],
+}
+
+impl<'de> DeserializeSeed<'de> for RowSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl RowSeed<'_> {
+    /// Finish a row that is not an object, which has no fields and so is
+    /// missing in every column.
+    fn empty_row<E>(self) -> Result<(), E>
+    where
+        E: de::Error,
+    {
+        self.seen.iter_mut().for_each(|s| *s = false);
+        self.reader.finish_row(self.seen).map_err(de::Error::custom)
+    }
+}
+
+impl<'de> Visitor<'de> for RowSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON row object")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<(), A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        self.seen.iter_mut().for_each(|s| *s = false);
+        push_fields(self.reader, self.seen, None, &mut map)?;
+        self.reader.finish_row(self.seen).map_err(de::Error::custom)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<(), A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+        self.empty_row()
+    }
+
+    fn visit_unit<E>(self) -> Result<(), E>
+    where
+        E: de::Error,
+    {
+        self.empty_row()
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<(), E>
+    where
+        E: de::Error,
+    {
+        self.empty_row()
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<(), E>
+    where
+        E: de::Error,
+    {
+        self.empty_row()
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<(), E>
+    where
+        E: de::Error,
+    {
+        self.empty_row()
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<(), E>
+    where
+        E: de::Error,
+    {
+        self.empty_row()
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<(), E>
+    where
+        E: de::Error,
+    {
+        self.empty_row()
+    }
+}
+
+/// Push the fields of a row object to their columns, marking them in `seen`.
+/// A field repeated within the object keeps its last value. Fields outside the
+/// schema are kept if the reader keeps them, and the field of column `skip`,
+/// filled from outside the object, is ignored.
+fn push_fields<'de, A>(
+    reader: &mut Reader,
+    seen: &mut [bool],
+    skip: Option<usize>,
+    map: &mut A,
+) -> Result<(), A::Error>
+where
+    A: MapAccess<'de>,
+{
+    while let Some(field) = map.next_key_seed(FieldSeed {
+        lookup: &reader.lookup,
+        keep_extra: reader.extra.is_some(),
+    })? {
+        match field {
+            Field::Column(i) if Some(i) == skip => {
+                map.next_value::<IgnoredAny>()?;
+            }
+            Field::Column(i) => {
+                reader.current = Some(i);
+                let val: Value = map.next_value()?;
+                reader
+                    .push_field(i, Some(&val), seen)
+                    .map_err(de::Error::custom)?;
+                reader.current = None;
+            }
+            Field::Extra(name) => {
+                let val: Value = map.next_value()?;
+                if let Some(extra) = reader.extra.as_mut() {
+                    extra.push(&name, reader.n_rows, val);
+                }
+            }
+            Field::Ignored => {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Walks a GeoJSON FeatureCollection, ignoring every member but `features`.
+struct CollectionSeed<'a> {
+    reader: &'a mut Reader,
+}
+
+impl<'de> DeserializeSeed<'de> for CollectionSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for CollectionSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a GeoJSON FeatureCollection")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<(), A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "features" {
+                map.next_value_seed(FeaturesSeed {
+                    reader: &mut *self.reader,
+                })?;
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct FeaturesSeed<'a> {
+    reader: &'a mut Reader,
+}
+
+impl<'de> DeserializeSeed<'de> for FeaturesSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for FeaturesSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON array of GeoJSON features")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<(), A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let geometry = self.reader.columns.iter().position(Column::is_geometry);
+        let mut seen = vec![false; self.reader.columns.len()];
+        while seq
+            .next_element_seed(FeatureSeed {
+                reader: self.reader,
+                geometry,
+                seen: &mut seen,
+            })?
+            .is_some()
+        {}
+        Ok(())
+    }
+}
+
+/// Reads one feature as a row, with `geometry` going to the `geometry` column
+/// and `properties` read like a row object.
+struct FeatureSeed<'a> {
+    reader: &'a mut Reader,
+    geometry: Option<usize>,
+    seen: &'a mut [bool],
+}
+
+impl<'de> DeserializeSeed<'de> for FeatureSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for FeatureSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a GeoJSON feature")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<(), A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        self.seen.iter_mut().for_each(|s| *s = false);
+
+        while let Some(key) = map.next_key::<String>()? {
+            match (key.as_str(), self.geometry) {
+                ("geometry", Some(i)) => {
+                    self.reader.current = Some(i);
+                    let val: Value = map.next_value()?;
+                    self.reader
+                        .push_field(i, Some(&val), self.seen)
+                        .map_err(de::Error::custom)?;
+                    self.reader.current = None;
+                }
+                ("properties", _) => {
+                    map.next_value_seed(PropertiesSeed {
+                        reader: &mut *self.reader,
+                        seen: &mut *self.seen,
+                        geometry: self.geometry,
+                    })?;
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        self.reader.finish_row(self.seen).map_err(de::Error::custom)
+    }
+}
+
+/// Walks a rows.json file, checking its columns in `meta` against the schema
+/// before streaming the rows of `data`.
+struct RowsFileSeed<'a> {
+    reader: &'a mut Reader,
+}
+
+impl<'de> DeserializeSeed<'de> for RowsFileSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for RowsFileSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a rows.json object with `meta` and `data`")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<(), A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut has_meta = false;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "meta" => {
+                    let meta: Meta = map.next_value()?;
+                    meta.check(&self.reader.col_names, &self.reader.col_types)
+                        .map_err(de::Error::custom)?;
+                    has_meta = true;
+                }
+                "data" => {
+                    if !has_meta {
+                        return Err(de::Error::custom("`data` comes before `meta`"));
+                    }
+                    map.next_value_seed(RowArraysSeed {
+                        reader: &mut *self.reader,
+                    })?;
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct RowArraysSeed<'a> {
+    reader: &'a mut Reader,
+}
+
+impl<'de> DeserializeSeed<'de> for RowArraysSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for RowArraysSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON array of row arrays")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<(), A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut seen = vec![false; self.reader.columns.len()];
+        while seq
+            .next_element_seed(RowArraySeed {
+                reader: self.reader,
+                seen: &mut seen,
+            })?
+            .is_some()
+        {}
+        Ok(())
+    }
+}
+
+/// Reads one positional row, sending each value to the column at its
+/// position.
+struct RowArraySeed<'a> {
+    reader: &'a mut Reader,
+    seen: &'a mut [bool],
+}
+
+impl<'de> DeserializeSeed<'de> for RowArraySeed<'_> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for RowArraySeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON row array")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<(), A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        self.seen.iter_mut().for_each(|s| *s = false);
+
+        let mut ended = false;
+        for i in 0..self.seen.len() {
+            self.reader.current = Some(i);
+            let val: Value = match seq.next_element()? {
+                Some(val) => val,
+                None => {
+                    ended = true;
+                    break;
+                }
+            };
+            let val = legacy_cell(&self.reader.col_types[i], val);
+            self.reader
+                .push_cell(i, Some(&val))
+                .map_err(de::Error::custom)?;
+            self.seen[i] = true;
+        }
+        self.reader.current = None;
+        if !ended {
+            while seq.next_element::<IgnoredAny>()?.is_some() {}
+        }
+
+        self.reader.finish_row(self.seen).map_err(de::Error::custom)
+    }
+}
+
+/// Reads the `properties` of a feature, which may be `null`, ignoring any
+/// property for the `geometry` column.
+struct PropertiesSeed<'a> {
+    reader: &'a mut Reader,
+    seen: &'a mut [bool],
+    geometry: Option<usize>,
+}
+
+impl<'de> DeserializeSeed<'de> for PropertiesSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_option(self)
+    }
+}
+
+impl<'de> Visitor<'de> for PropertiesSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a GeoJSON properties object")
+    }
+
+    fn visit_none<E>(self) -> Result<(), E>
+    where
+        E: de::Error,
+    {
+        Ok(())
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_map(self)
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<(), A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        push_fields(self.reader, self.seen, self.geometry, &mut map)
+    }
+}
+
+/// A row object key, resolved against the schema.
+enum Field {
+    Column(usize),
+    /// A key outside the schema, when those are kept.
+    Extra(String),
+    Ignored,
+}
+
+/// Resolves an object key to its column index, only allocating for keys
+/// outside the schema that are kept.
+struct FieldSeed<'a> {
+    lookup: &'a HashMap<String, usize>,
+    keep_extra: bool,
+}
+
+impl<'de> DeserializeSeed<'de> for FieldSeed<'_> {
+    type Value = Field;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Field, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'de> Visitor<'de> for FieldSeed<'_> {
+    type Value = Field;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a field name")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Field, E>
+    where
+        E: de::Error,
+    {
+        Ok(match self.lookup.get(v) {
+            Some(&i) => Field::Column(i),
+            None if self.keep_extra => Field::Extra(v.to_string()),
+            None => Field::Ignored,
+        })
+    }
+}
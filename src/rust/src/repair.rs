@@ -0,0 +1,165 @@
+//! Optional repair of line and polygon geometries as they are parsed.
+//!
+//! Socrata accepts polygons that GEOS rejects: rings that are not closed,
+//! repeated vertices, rings with no area, either winding order, and vertices
+//! without coordinates. Repairs fix each of these and record what was wrong,
+//! so that a geometry column can be used for spatial operations without
+//! stopping at the first bad row. Self-intersections are not detected.
+
+use crate::geom::Coord;
+use crate::process::Column;
+
+/// The problems found in a geometry, as a set of flags.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Issues(u8);
+
+impl Issues {
+    pub const NAN_VERTEX: Issues = Issues(1);
+    pub const DUPLICATE_VERTEX: Issues = Issues(1 << 1);
+    pub const UNCLOSED_RING: Issues = Issues(1 << 2);
+    pub const DEGENERATE: Issues = Issues(1 << 3);
+    pub const WRONG_ORIENTATION: Issues = Issues(1 << 4);
+
+    const REASONS: [(Issues, &'static str); 5] = [
+        (Issues::NAN_VERTEX, "NaN vertex"),
+        (Issues::DUPLICATE_VERTEX, "Duplicate vertex"),
+        (Issues::UNCLOSED_RING, "Unclosed ring"),
+        (Issues::DEGENERATE, "Degenerate ring or line"),
+        (Issues::WRONG_ORIENTATION, "Wrong ring orientation"),
+    ];
+
+    fn insert(&mut self, issue: Issues) {
+        self.0 |= issue.0;
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// The problems as a `; `-separated description, or `None` if there are
+    /// none.
+    pub fn reason(self) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+        let reasons: Vec<&str> = Issues::REASONS
+            .iter()
+            .filter(|(issue, _)| self.0 & issue.0 != 0)
+            .map(|(_, reason)| *reason)
+            .collect();
+        Some(reasons.join("; "))
+    }
+}
+
+impl Column {
+    /// Whether the geometries of the column can be repaired.
+    pub fn is_repairable(&self) -> bool {
+        matches!(
+            self,
+            Column::Line(_) | Column::Polygon(_) | Column::MultiLine(_) | Column::MultiPolygon(_)
+        )
+    }
+
+    /// Repair the last geometry pushed, returning what was wrong with it, or
+    /// `None` if it is missing or the column cannot be repaired.
+    pub fn repair_last(&mut self) -> Option<Issues> {
+        let mut issues = Issues::default();
+        match self {
+            Column::Line(v) => {
+                let line = v.last_mut()?.as_mut()?;
+                if !repair_line(line, &mut issues) {
+                    line.clear();
+                }
+            }
+            Column::MultiLine(v) => {
+                let lines = v.last_mut()?.as_mut()?;
+                lines.retain_mut(|line| repair_line(line, &mut issues));
+            }
+            Column::Polygon(v) => repair_polygon(v.last_mut()?.as_mut()?, &mut issues),
+            Column::MultiPolygon(v) => {
+                let polygons = v.last_mut()?.as_mut()?;
+                polygons.retain_mut(|polygon| {
+                    repair_polygon(polygon, &mut issues);
+                    !polygon.is_empty()
+                });
+            }
+            _ => return None,
+        }
+        Some(issues)
+    }
+}
+
+/// Drop vertices without x or y, and vertices equal to the one before.
+fn clean(coords: &mut Vec<Coord>, issues: &mut Issues) {
+    let n = coords.len();
+    coords.retain(|coord| !coord.x.is_nan() && !coord.y.is_nan());
+    if coords.len() < n {
+        issues.insert(Issues::NAN_VERTEX);
+    }
+
+    let n = coords.len();
+    coords.dedup_by(|a, b| a.x == b.x && a.y == b.y);
+    if coords.len() < n {
+        issues.insert(Issues::DUPLICATE_VERTEX);
+    }
+}
+
+/// Repair a line, returning whether it still has at least two vertices.
+fn repair_line(line: &mut Vec<Coord>, issues: &mut Issues) -> bool {
+    clean(line, issues);
+    if line.len() < 2 {
+        issues.insert(Issues::DEGENERATE);
+        return false;
+    }
+    true
+}
+
+/// Repair a polygon, dropping degenerate holes, or every ring if the
+/// exterior ring is degenerate.
+fn repair_polygon(rings: &mut Vec<Vec<Coord>>, issues: &mut Issues) {
+    if rings.is_empty() {
+        return;
+    }
+    if !repair_ring(&mut rings[0], true, issues) {
+        rings.clear();
+        return;
+    }
+
+    let mut i = 0;
+    rings.retain_mut(|ring| {
+        i += 1;
+        i == 1 || repair_ring(ring, false, issues)
+    });
+}
+
+/// Close a ring and orient it counterclockwise if it is an exterior ring, or
+/// clockwise if it is a hole, as the OGC Simple Features specification does.
+/// Returns whether the ring encloses any area.
+fn repair_ring(ring: &mut Vec<Coord>, exterior: bool, issues: &mut Issues) -> bool {
+    clean(ring, issues);
+    if let (Some(&first), Some(last)) = (ring.first(), ring.last()) {
+        if first.x != last.x || first.y != last.y {
+            ring.push(first);
+            issues.insert(Issues::UNCLOSED_RING);
+        }
+    }
+
+    let area = signed_area(ring);
+    if ring.len() < 4 || area == 0.0 || area.is_nan() {
+        issues.insert(Issues::DEGENERATE);
+        return false;
+    }
+    if (area > 0.0) != exterior {
+        ring.reverse();
+        issues.insert(Issues::WRONG_ORIENTATION);
+    }
+    true
+}
+
+/// Twice the signed area of a closed ring, positive when it is
+/// counterclockwise.
+fn signed_area(ring: &[Coord]) -> f64 {
+    ring.windows(2)
+        .map(|pair| pair[0].x * pair[1].y - pair[1].x * pair[0].y)
+        .sum()
+}
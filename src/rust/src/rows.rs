@@ -0,0 +1,132 @@
+//! The legacy `/api/views/{id}/rows.json` export format, which carries its
+//! schema in `meta.view.columns` and its rows as positional arrays in `data`.
+
+use std::collections::HashSet;
+
+use chrono::SecondsFormat;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::error::ParseError;
+use crate::infer::system_type;
+use crate::time::from_epoch_seconds;
+
+/// Datatype of the system columns (`:sid`, `:position`, `:created_at`, ...)
+/// that lead every row.
+const SYSTEM_TYPE: &str = "meta_data";
+
+#[derive(Deserialize)]
+struct RowsFile {
+    meta: Meta,
+}
+
+#[derive(Deserialize)]
+pub struct Meta {
+    view: View,
+}
+
+#[derive(Deserialize)]
+struct View {
+    columns: Vec<ViewColumn>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ViewColumn {
+    field_name: String,
+    data_type_name: String,
+}
+
+impl Meta {
+    /// Field names and Socrata datatypes of the row positions, in order.
+    /// System columns are typed by name.
+    pub fn schema(&self) -> (Vec<String>, Vec<String>) {
+        self.view
+            .columns
+            .iter()
+            .map(|col| {
+                let ty = if col.data_type_name == SYSTEM_TYPE {
+                    system_column_type(&col.field_name)
+                } else {
+                    &col.data_type_name
+                };
+                (col.field_name.clone(), ty.to_string())
+            })
+            .unzip()
+    }
+
+    /// Check that the columns have distinct names and match the schema
+    /// `col_names` and `col_types` read from the first file.
+    pub fn check(&self, col_names: &[String], col_types: &[String]) -> Result<(), String> {
+        let mut seen = HashSet::new();
+        if let Some(col) = self
+            .view
+            .columns
+            .iter()
+            .find(|col| !seen.insert(col.field_name.as_str()))
+        {
+            return Err(format!(
+                "field `{}` is repeated in `meta.view.columns`",
+                col.field_name
+            ));
+        }
+        let (names, types) = self.schema();
+        if names != col_names || types != col_types {
+            return Err("`meta.view.columns` differs from the first file".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// The datatype of a system column, which rows.json types as `meta_data`.
+fn system_column_type(name: &str) -> &'static str {
+    match name {
+        ":position" => "number",
+        name => system_type(name).unwrap_or("text"),
+    }
+}
+
+/// Read the field names and Socrata datatypes of the columns of a rows.json
+/// file, skipping its `data`.
+pub fn rows_schema(bytes: &[u8]) -> Result<(Vec<String>, Vec<String>), ParseError> {
+    let file: RowsFile =
+        serde_json::from_slice(bytes).map_err(|err| ParseError::json(1, bytes, None, err))?;
+    Ok(file.meta.schema())
+}
+
+/// Convert a rows.json cell to the shape the JSON endpoints send.
+///
+/// Compound values are positional arrays in rows.json: locations are
+/// `[human_address, latitude, longitude, machine_address, needs_recoding]`,
+/// urls `[url, description]` and phones `[phone_number, phone_type]`. Arrays
+/// with no values are missing. System columns hold plain numbers, with
+/// `:created_at` and `:updated_at` in seconds since the epoch.
+pub fn legacy_cell(col_type: &str, val: Value) -> Value {
+    if let (Value::Number(n), "fixed_timestamp") = (&val, col_type) {
+        return match n.as_f64().and_then(from_epoch_seconds) {
+            Some(instant) => Value::String(instant.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+            None => val,
+        };
+    }
+    let fields: &[&str] = match col_type {
+        "location" => &["human_address", "latitude", "longitude"],
+        "url" => &["url", "description"],
+        "phone" => &["phone_number", "phone_type"],
+        _ => return val,
+    };
+    let items = match val {
+        Value::Array(items) => items,
+        val => return val,
+    };
+    let object: Map<String, Value> = fields
+        .iter()
+        .zip(items)
+        .filter(|(_, item)| !item.is_null())
+        .map(|(field, item)| (field.to_string(), item))
+        .collect();
+    if object.is_empty() {
+        Value::Null
+    } else {
+        Value::Object(object)
+    }
+}
@@ -0,0 +1,142 @@
+//! Resolution of Socrata floating timestamps, which are wall-clock times
+//! without a zone.
+
+use chrono::{DateTime, Duration, LocalResult, NaiveDateTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// How to resolve wall-clock times that fall in a daylight saving time fold
+/// (they occur twice) or gap (they never occur).
+#[derive(Clone, Copy)]
+pub enum Ambiguous {
+    /// The earlier of two times in a fold, and the end of a gap.
+    Earliest,
+    /// The later of two times in a fold, and the end of a gap.
+    Latest,
+    /// The later of two times in a fold, and times in a gap shifted forward
+    /// by the length of the gap.
+    ShiftForward,
+    /// A missing value.
+    Na,
+    /// A parse error.
+    Error,
+}
+
+impl Ambiguous {
+    pub fn from_name(name: &str) -> Option<Ambiguous> {
+        match name {
+            "earliest" => Some(Ambiguous::Earliest),
+            "latest" => Some(Ambiguous::Latest),
+            "shift-forward" => Some(Ambiguous::ShiftForward),
+            "NA" => Some(Ambiguous::Na),
+            "error" => Some(Ambiguous::Error),
+            _ => None,
+        }
+    }
+}
+
+/// How floating timestamps are interpreted.
+#[derive(Clone, Copy)]
+pub enum Floating {
+    /// Resolve wall-clock times to instants in `tz`. `session` is set when
+    /// `tz` is the R session timezone, so that R can keep following it.
+    Zoned {
+        tz: Tz,
+        ambiguous: Ambiguous,
+        session: bool,
+    },
+    /// Keep wall-clock times as they are, stored as if they were UTC.
+    Naive,
+}
+
+impl Floating {
+    /// The instant of a wall-clock time.
+    pub fn resolve(self, naive: &NaiveDateTime) -> Result<Option<DateTime<Utc>>, String> {
+        match self {
+            Floating::Zoned { tz, ambiguous, .. } => resolve_local(naive, tz, ambiguous),
+            Floating::Naive => Ok(Some(naive.and_utc())),
+        }
+    }
+
+    /// The wall-clock time that resolved to `instant`.
+    pub fn wall_clock(self, instant: DateTime<Utc>) -> NaiveDateTime {
+        let utc = instant.naive_utc();
+        match self {
+            Floating::Zoned { tz, .. } => tz.from_utc_datetime(&utc).naive_local(),
+            Floating::Naive => utc,
+        }
+    }
+}
+
+fn resolve_local(
+    naive: &NaiveDateTime,
+    tz: Tz,
+    ambiguous: Ambiguous,
+) -> Result<Option<DateTime<Utc>>, String> {
+    match tz.from_local_datetime(naive) {
+        LocalResult::Single(dt) => Ok(Some(dt.with_timezone(&Utc))),
+        LocalResult::Ambiguous(a, b) => {
+            let (earliest, latest) = if a <= b { (a, b) } else { (b, a) };
+            match ambiguous {
+                Ambiguous::Earliest => Ok(Some(earliest.with_timezone(&Utc))),
+                Ambiguous::Latest | Ambiguous::ShiftForward => Ok(Some(latest.with_timezone(&Utc))),
+                Ambiguous::Na => Ok(None),
+                Ambiguous::Error => Err(format!("{} is ambiguous in {}", naive, tz.name())),
+            }
+        }
+        LocalResult::None => match ambiguous {
+            Ambiguous::Earliest | Ambiguous::Latest => Ok(gap_end(naive, tz)),
+            Ambiguous::ShiftForward => Ok(shift_forward(naive, tz)),
+            Ambiguous::Na => Ok(None),
+            Ambiguous::Error => Err(format!("{} does not exist in {}", naive, tz.name())),
+        },
+    }
+}
+
+/// UTC offset in seconds at a (roughly known) instant.
+fn offset_at(tz: Tz, utc: &NaiveDateTime) -> i64 {
+    tz.offset_from_utc_datetime(utc).fix().local_minus_utc() as i64
+}
+
+/// Interpret a time in a gap with the offset in effect before the gap, which
+/// moves it forward by the length of the gap.
+fn shift_forward(naive: &NaiveDateTime, tz: Tz) -> Option<DateTime<Utc>> {
+    let before = offset_at(tz, &(*naive - Duration::days(1)));
+    Some((*naive - Duration::seconds(before)).and_utc())
+}
+
+/// The first instant after a gap, found by bisecting whole seconds between
+/// the interpretations with the offsets after and before the gap.
+fn gap_end(naive: &NaiveDateTime, tz: Tz) -> Option<DateTime<Utc>> {
+    let before = offset_at(tz, &(*naive - Duration::days(1)));
+    let after = offset_at(tz, &(*naive + Duration::days(1)));
+    let local = naive.and_utc().timestamp();
+
+    let (mut lo, mut hi) = (local - after, local - before);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        let utc = DateTime::from_timestamp(mid, 0)?.naive_utc();
+        if offset_at(tz, &utc) == after {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    DateTime::from_timestamp(lo, 0)
+}
+
+/// The instant `secs` seconds after the epoch, to the nearest nanosecond.
+pub fn from_epoch_seconds(secs: f64) -> Option<DateTime<Utc>> {
+    const NANOS: f64 = 1e9;
+    let whole = secs.floor();
+    if !whole.is_finite() || whole.abs() >= i64::MAX as f64 {
+        return None;
+    }
+    let nanos = ((secs - whole) * NANOS).round();
+    // rounding can carry a whole second over
+    let (whole, nanos) = if nanos >= NANOS {
+        (whole + 1.0, 0.0)
+    } else {
+        (whole, nanos)
+    };
+    DateTime::from_timestamp(whole as i64, nanos as u32)
+}
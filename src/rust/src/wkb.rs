@@ -0,0 +1,78 @@
+//! Little-endian well-known binary (WKB) encoding of parsed geometries.
+//!
+//! Geometries with z or m ordinates use the ISO type codes (e.g. 1001 for a
+//! point with z).
+
+use crate::geom::{Coord, Dim};
+
+const POINT: u32 = 1;
+const LINESTRING: u32 = 2;
+const POLYGON: u32 = 3;
+const MULTIPOINT: u32 = 4;
+const MULTILINESTRING: u32 = 5;
+const MULTIPOLYGON: u32 = 6;
+
+fn write_header(out: &mut Vec<u8>, geom_type: u32, dim: Dim) {
+    let code = geom_type + if dim.z { 1000 } else { 0 } + if dim.m { 2000 } else { 0 };
+    out.push(1);
+    out.extend_from_slice(&code.to_le_bytes());
+}
+
+fn write_count(out: &mut Vec<u8>, n: usize) {
+    out.extend_from_slice(&(n as u32).to_le_bytes());
+}
+
+fn write_coord(out: &mut Vec<u8>, coord: Coord, dim: Dim) {
+    for ordinate in dim.ordinates(coord) {
+        out.extend_from_slice(&ordinate.to_le_bytes());
+    }
+}
+
+fn write_coords(out: &mut Vec<u8>, coords: &[Coord], dim: Dim) {
+    write_count(out, coords.len());
+    for coord in coords {
+        write_coord(out, *coord, dim);
+    }
+}
+
+pub fn write_point(out: &mut Vec<u8>, point: Coord, dim: Dim) {
+    write_header(out, POINT, dim);
+    write_coord(out, point, dim);
+}
+
+pub fn write_linestring(out: &mut Vec<u8>, line: &[Coord], dim: Dim) {
+    write_header(out, LINESTRING, dim);
+    write_coords(out, line, dim);
+}
+
+pub fn write_polygon(out: &mut Vec<u8>, rings: &[Vec<Coord>], dim: Dim) {
+    write_header(out, POLYGON, dim);
+    write_count(out, rings.len());
+    for ring in rings {
+        write_coords(out, ring, dim);
+    }
+}
+
+pub fn write_multipoint(out: &mut Vec<u8>, points: &[Coord], dim: Dim) {
+    write_header(out, MULTIPOINT, dim);
+    write_count(out, points.len());
+    for point in points {
+        write_point(out, *point, dim);
+    }
+}
+
+pub fn write_multilinestring(out: &mut Vec<u8>, lines: &[Vec<Coord>], dim: Dim) {
+    write_header(out, MULTILINESTRING, dim);
+    write_count(out, lines.len());
+    for line in lines {
+        write_linestring(out, line, dim);
+    }
+}
+
+pub fn write_multipolygon(out: &mut Vec<u8>, polygons: &[Vec<Vec<Coord>>], dim: Dim) {
+    write_header(out, MULTIPOLYGON, dim);
+    write_count(out, polygons.len());
+    for polygon in polygons {
+        write_polygon(out, polygon, dim);
+    }
+}
@@ -0,0 +1,91 @@
+//! Well-known text (WKT) encoding of parsed geometries.
+
+use std::fmt::Write;
+
+use crate::geom::{Coord, Dim};
+
+/// Write the geometry type, tagged with its dimension (e.g. `POINT Z `).
+fn write_tag(out: &mut String, geom_type: &str, dim: Dim) {
+    out.push_str(geom_type);
+    match (dim.z, dim.m) {
+        (false, false) => out.push(' '),
+        (true, false) => out.push_str(" Z "),
+        (false, true) => out.push_str(" M "),
+        (true, true) => out.push_str(" ZM "),
+    }
+}
+
+fn write_coord(out: &mut String, coord: Coord, dim: Dim) {
+    for (i, ordinate) in dim.ordinates(coord).enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        write!(out, "{}", ordinate).unwrap();
+    }
+}
+
+/// Write `items` as a parenthesized, comma-separated list, or `EMPTY`.
+fn write_list<T>(out: &mut String, items: &[T], mut write_item: impl FnMut(&mut String, &T)) {
+    if items.is_empty() {
+        out.push_str("EMPTY");
+        return;
+    }
+    out.push('(');
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write_item(out, item);
+    }
+    out.push(')');
+}
+
+fn write_coords(out: &mut String, coords: &[Coord], dim: Dim) {
+    write_list(out, coords, |out, coord| write_coord(out, *coord, dim));
+}
+
+fn write_rings(out: &mut String, rings: &[Vec<Coord>], dim: Dim) {
+    write_list(out, rings, |out, ring| write_coords(out, ring, dim));
+}
+
+/// An empty point, which is held with NaN coordinates as in WKB, is written as
+/// `POINT EMPTY`.
+pub fn write_point(out: &mut String, point: Coord, dim: Dim) {
+    write_tag(out, "POINT", dim);
+    if point.x.is_nan() && point.y.is_nan() {
+        out.push_str("EMPTY");
+        return;
+    }
+    out.push('(');
+    write_coord(out, point, dim);
+    out.push(')');
+}
+
+pub fn write_linestring(out: &mut String, line: &[Coord], dim: Dim) {
+    write_tag(out, "LINESTRING", dim);
+    write_coords(out, line, dim);
+}
+
+pub fn write_polygon(out: &mut String, rings: &[Vec<Coord>], dim: Dim) {
+    write_tag(out, "POLYGON", dim);
+    write_rings(out, rings, dim);
+}
+
+pub fn write_multipoint(out: &mut String, points: &[Coord], dim: Dim) {
+    write_tag(out, "MULTIPOINT", dim);
+    write_list(out, points, |out, point| {
+        out.push('(');
+        write_coord(out, *point, dim);
+        out.push(')');
+    });
+}
+
+pub fn write_multilinestring(out: &mut String, lines: &[Vec<Coord>], dim: Dim) {
+    write_tag(out, "MULTILINESTRING", dim);
+    write_rings(out, lines, dim);
+}
+
+pub fn write_multipolygon(out: &mut String, polygons: &[Vec<Vec<Coord>>], dim: Dim) {
+    write_tag(out, "MULTIPOLYGON", dim);
+    write_list(out, polygons, |out, polygon| write_rings(out, polygon, dim));
+}